@@ -1,5 +1,6 @@
-use glam::{Vec2, Vec3};
+use glam::{vec2, vec3, Vec2, Vec3};
 use std::convert::TryInto;
+use std::ops::Range;
 
 #[derive(Default, Copy, Clone)]
 #[repr(C)]
@@ -13,8 +14,17 @@ macro_rules! art {
     ( $( $enum:ident : $field:ident ; )* ) => {
         #[derive(Copy, Clone, Debug)]
         pub enum Art { $( $enum, )* }
+        impl Art {
+            /// The Art made from the mesh called `name` in `train.glb`.
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $( stringify!($field) => Some(Art::$enum), )*
+                    _ => None,
+                }
+            }
+        }
 
-        #[derive(Default)]
+        #[derive(Default, Clone, Copy)]
         pub struct ArtIndices { $( $field: (i32, i32), )* }
         impl ArtIndices {
             /// Returns the indices for this Art
@@ -26,7 +36,7 @@ macro_rules! art {
         }
 
         #[cfg(feature = "gltf")]
-        #[derive(Default)]
+        #[derive(Default, Clone, Copy)]
         pub struct ArtIndicesBuilder { $( $field: Option<(i32, i32)>, )* }
 
         #[cfg(feature = "gltf")]
@@ -53,6 +63,8 @@ art! {
     Train: train;
     Wheel: wheel;
     Gun: gun;
+    Shell: shell;
+    Flash: flash;
 }
 
 #[derive(Debug, Copy, Clone, Default)]
@@ -78,14 +90,71 @@ impl BezierPoint {
     pub fn pos(&self) -> Vec2 {
         self.pos.into()
     }
+
+    /// Writes out tracks the same way `track_script.py` does, ready to be put in `track.json`.
+    pub fn to_json(tracks: &[Vec<BezierPoint>]) -> String {
+        let point = |p: &BezierPoint| {
+            let BezierPoint { left: (lx, ly), pos: (px, py), right: (rx, ry) } = *p;
+            format!(
+                r#"{{"left": [{}, {}], "pos": [{}, {}], "right": [{}, {}]}}"#,
+                lx, ly, px, py, rx, ry
+            )
+        };
+        let tracks: Vec<String> = tracks
+            .iter()
+            .map(|points| {
+                let points: Vec<String> = points.iter().map(point).collect();
+                format!("[{}]", points.join(", "))
+            })
+            .collect();
+        format!("[{}]", tracks.join(", "))
+    }
+
+    /// `json` with its first track swapped for `points`, if it's a list of tracks.
+    pub fn replace_first_json(json: &str, points: &[BezierPoint]) -> Option<String> {
+        let mut depth = 0;
+        let mut first = None;
+        for (i, c) in json.char_indices() {
+            match c {
+                '[' => {
+                    depth += 1;
+                    if depth == 2 && first.is_none() {
+                        first = Some(i);
+                    }
+                }
+                ']' => {
+                    depth -= 1;
+                    match (depth, first) {
+                        (1, Some(start)) => {
+                            let ours = Self::to_json(&[points.to_vec()]);
+                            let ours = &ours[1..ours.len() - 1];
+                            return Some(format!("{}{}{}", &json[..start], ours, &json[i + 1..]));
+                        }
+                        (0, None) => return Some(Self::to_json(&[points.to_vec()])),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
 }
 
+pub const TRACK_CURVE_COUNT: usize = 256;
+
+pub const LOOP_GAP: f32 = 0.1;
+
+/// The curves of a track, the way they're kept in a cedset.
 #[repr(C)]
-pub struct Track<const N: usize>([BezierCurve; N]);
+pub struct TrackCurves {
+    curves: [BezierCurve; TRACK_CURVE_COUNT],
+    len: u32,
+}
 
-impl<const N: usize> Default for Track<N> {
+impl Default for TrackCurves {
     fn default() -> Self {
-        Track([Default::default(); N])
+        TrackCurves { curves: [Default::default(); TRACK_CURVE_COUNT], len: 0 }
     }
 }
 
@@ -112,49 +181,349 @@ impl BezierCurve {
         thlerp(start, left, right, t).lerp(thlerp(left, right, end, t), t)
     }
 
+    pub fn derivative(&self, t: f32) -> Vec2 {
+        let &Self { start, left, right, end } = self;
+        let u = 1.0 - t;
+        ((left - start) * u * u + (right - left) * 2.0 * u * t + (end - right) * t * t) * 3.0
+    }
+
+    pub fn second_derivative(&self, t: f32) -> Vec2 {
+        let &Self { start, left, right, end } = self;
+        ((right - left * 2.0 + start) * (1.0 - t) + (end - right * 2.0 + left) * t) * 6.0
+    }
+
+    /// One over the radius the curve turns at `t`, positive counterclockwise.
+    pub fn curvature(&self, t: f32) -> f32 {
+        let d1 = self.derivative(t);
+        d1.perp_dot(self.second_derivative(t)) / d1.length().powi(3).max(f32::EPSILON)
+    }
+
     pub fn len(&self) -> f32 {
+        self.len_to(1.0)
+    }
+
+    pub fn len_to(&self, t: f32) -> f32 {
         (0..51)
-            .map(|n| self.point(n as f32 / 50.0))
+            .map(|n| self.point(n as f32 / 50.0 * t))
             .fold((self.start, 0.0), |(p0, len), p1| (p1, (p0 - p1).length() + len))
             .1
     }
+
+    /// The `t` of the point on the curve nearest to `p`.
+    pub fn closest(&self, p: Vec2) -> f32 {
+        let mut t = (0..=16)
+            .map(|n| n as f32 / 16.0)
+            .min_by(|&a, &b| {
+                let (a, b) = (self.point(a).distance_squared(p), self.point(b).distance_squared(p));
+                a.partial_cmp(&b).unwrap()
+            })
+            .unwrap();
+
+        // Newton's method.
+        for _ in 0..4 {
+            let to = self.point(t) - p;
+            let (d1, d2) = (self.derivative(t), self.second_derivative(t));
+            let slope = d1.length_squared() + to.dot(d2);
+            if slope.abs() < f32::EPSILON {
+                break;
+            }
+            t = (t - to.dot(d1) / slope).clamp(0.0, 1.0);
+        }
+
+        t
+    }
+
+    /// Where each straight piece within `tolerance` of the curve ends, after `self.start`.
+    pub fn tessellate(&self, tolerance: f32) -> Vec<Vec2> {
+        let mut points = Vec::new();
+        self.tessellate_between(0.0, 0.5, tolerance, &mut points);
+        self.tessellate_between(0.5, 1.0, tolerance, &mut points);
+        points
+    }
+
+    fn tessellate_between(&self, t0: f32, t1: f32, tolerance: f32, out: &mut Vec<Vec2>) {
+        let (from, to) = (self.point(t0), self.point(t1));
+        let chord = to - from;
+        let chord_error = |t: f32| {
+            let p = self.point(t0 + (t1 - t0) * t);
+            let along = (p - from).dot(chord) / chord.length_squared().max(f32::EPSILON);
+            p.distance(from + chord * along.clamp(0.0, 1.0))
+        };
+
+        let worst = [0.25, 0.5, 0.75].iter().map(|&t| chord_error(t)).fold(0.0, f32::max);
+        if worst > tolerance && t1 - t0 > 1.0 / 65536.0 {
+            let mid = (t0 + t1) / 2.0;
+            self.tessellate_between(t0, mid, tolerance, out);
+            self.tessellate_between(mid, t1, tolerance, out);
+        } else {
+            out.push(to);
+        }
+    }
 }
 
-impl<const N: usize> Track<N> {
+impl TrackCurves {
     pub fn from_points(points: &[BezierPoint]) -> Self {
-        let mut pairs = points.windows(2);
-        let mut got = 0;
-        Track([(); N].map(|_| {
-            if let Some(&[start, end]) = pairs.next() {
-                got += 1;
-                BezierCurve::new(start, end)
-            } else {
-                panic!("ran out of bezier points! \nexpected: {}\ngot: {}", N, got)
-            }
-        }))
+        let mut track = Self::default();
+        for (curve, pair) in track.curves.iter_mut().zip(points.windows(2)) {
+            *curve = BezierCurve::new(pair[0], pair[1]);
+            track.len += 1;
+        }
+
+        let got = points.len().saturating_sub(1);
+        if got > TRACK_CURVE_COUNT {
+            panic!("too many bezier points! \nspace for: {}\ngot: {}", TRACK_CURVE_COUNT, got)
+        }
+        track
+    }
+
+    pub fn to_track(&self) -> Track {
+        Track::new(self.curves[..self.len as usize].to_vec())
+    }
+}
+
+/// A line of curves, with their lengths and a grid for finding them by position.
+pub struct Track {
+    curves: Vec<BezierCurve>,
+    /// Where each curve starts, then the track's length.
+    starts: Vec<f32>,
+    /// The length of each curve up to every `PACE_STEPS`th of its `t`.
+    paces: Vec<Vec<f32>>,
+    grid: TrackGrid,
+}
+
+/// The same as `BezierCurve::len_to` uses.
+const PACE_STEPS: usize = 50;
+
+impl Track {
+    pub fn from_points(points: &[BezierPoint]) -> Self {
+        Self::new(points.windows(2).map(|pair| BezierCurve::new(pair[0], pair[1])).collect())
+    }
+
+    pub fn new(curves: Vec<BezierCurve>) -> Self {
+        let paces: Vec<Vec<f32>> = curves
+            .iter()
+            .map(|curve| {
+                let points = (0..=PACE_STEPS).map(|n| curve.point(n as f32 / PACE_STEPS as f32));
+                let mut len = 0.0;
+                let mut last = curve.start;
+                points
+                    .map(|p| {
+                        len += (p - last).length();
+                        last = p;
+                        len
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut starts = vec![0.0];
+        for pace in &paces {
+            starts.push(starts.last().unwrap() + pace.last().unwrap());
+        }
+
+        Self { grid: TrackGrid::new(&curves), curves, starts, paces }
+    }
+
+    pub fn curves(&self) -> &[BezierCurve] {
+        &self.curves
+    }
+
+    /// The points the curves run between, with the unused end handles mirrored.
+    pub fn points(&self) -> Vec<BezierPoint> {
+        let mut points = vec![];
+        let mut left = None;
+        for c in &self.curves {
+            let pos = c.start;
+            let left = left.replace(c.right).unwrap_or(pos * 2.0 - c.left);
+            points.push(BezierPoint { left: left.into(), pos: pos.into(), right: c.left.into() });
+        }
+        if let Some(c) = self.curves.last() {
+            let (left, pos) = (c.right, c.end);
+            let right = pos * 2.0 - left;
+            points.push(BezierPoint { left: left.into(), pos: pos.into(), right: right.into() });
+        }
+        points
     }
 
     pub fn len(&self) -> f32 {
-        self.0.iter().map(|curve| curve.len()).sum()
+        *self.starts.last().unwrap()
+    }
+
+    pub fn is_loop(&self) -> bool {
+        match (self.curves.first(), self.curves.last()) {
+            (Some(first), Some(last)) => first.start.distance(last.end) < LOOP_GAP,
+            _ => false,
+        }
     }
 
     pub fn point(&self, t: f32) -> Vec2 {
         let total = self.len();
-        let mut so_far = 0.0;
-        for segment in &self.0 {
+        for (segment, &start) in self.curves.iter().zip(&self.starts) {
             let len = segment.len() / total;
+            let so_far = start / total;
             if so_far + len >= t {
                 return segment.point((t - so_far) / len);
             }
-            so_far += len;
         }
 
         Vec2::zero()
     }
+
+    /// Like `BezierCurve::curvature`, `distance` along the track.
+    pub fn curvature(&self, distance: f32) -> f32 {
+        let distance = if self.is_loop() { distance.rem_euclid(self.len()) } else { distance };
+        let i = self.starts.partition_point(|&start| start <= distance).saturating_sub(1);
+        let i = i.min(self.curves.len() - 1);
+
+        let (pace, along) = (&self.paces[i], distance - self.starts[i]);
+        let n = pace.partition_point(|&len| len < along).clamp(1, PACE_STEPS);
+        let (before, after) = (pace[n - 1], pace[n]);
+        let within = if after > before {
+            ((along - before) / (after - before)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        self.curves[i].curvature((n as f32 - 1.0 + within) / PACE_STEPS as f32)
+    }
+
+    /// The track as a polyline within `tolerance` of it.
+    pub fn tessellate(&self, tolerance: f32) -> Vec<Vec2> {
+        let mut points = Vec::new();
+        for curve in &self.curves {
+            if points.is_empty() {
+                points.push(curve.start);
+            }
+            points.extend(curve.tessellate(tolerance));
+        }
+        points
+    }
+
+    pub fn path(&self, range: Range<f32>, tolerance: f32) -> Vec<PathPoint> {
+        PathPoint::clip(&PathPoint::along(&self.tessellate(tolerance)), range)
+    }
+
+    /// The curves sharing a grid square with curve `i`, which are all it could cross.
+    pub fn curves_near(&self, i: usize) -> Vec<usize> {
+        let grid = &self.grid;
+        let &BezierCurve { start, left, right, end } = &self.curves[i];
+        let (x0, y0) = grid.cell_of(start.min(left).min(right).min(end));
+        let (x1, y1) = grid.cell_of(start.max(left).max(right).max(end));
+        let mut near: Vec<usize> = (y0..=y1)
+            .flat_map(|y| (x0..=x1).map(move |x| (x, y)))
+            .flat_map(|(x, y)| grid.cells[y * grid.width + x].iter().copied())
+            .collect();
+        near.sort_unstable();
+        near.dedup();
+        near
+    }
+
+    /// How far along the track `point` is, and how far off to its left.
+    pub fn project(&self, point: Vec2) -> (f32, f32) {
+        let mut best: Option<(f32, usize, f32)> = None;
+        let grid = &self.grid;
+        let center = grid.cell_of(point);
+        let mut ring = 0;
+        loop {
+            for i in grid.ring(center, ring) {
+                let curve = &self.curves[i];
+                let t = curve.closest(point);
+                let dist = curve.point(t).distance(point);
+                match best {
+                    Some((best_dist, ..)) if best_dist <= dist => {}
+                    _ => best = Some((dist, i, t)),
+                }
+            }
+
+            let done = matches!(best, Some((dist, ..)) if dist <= ring as f32 * grid.cell_size);
+            if done || ring > grid.width.max(grid.height) {
+                break;
+            }
+            ring += 1;
+        }
+
+        let (_, i, t) = match best {
+            Some(best) => best,
+            None => return (0.0, 0.0),
+        };
+        let curve = &self.curves[i];
+        let side = curve.derivative(t).normalize().perp();
+        (self.starts[i] + curve.len_to(t), (point - curve.point(t)).dot(side))
+    }
+}
+
+/// Buckets curves by the grid squares their control points reach into.
+struct TrackGrid {
+    min: Vec2,
+    cell_size: f32,
+    width: usize,
+    height: usize,
+    cells: Vec<Vec<usize>>,
+}
+
+impl TrackGrid {
+    const CELL_SIZE: f32 = 16.0;
+
+    fn new(curves: &[BezierCurve]) -> Self {
+        let bounds = |c: &BezierCurve| {
+            let &BezierCurve { start, left, right, end } = c;
+            (start.min(left).min(right).min(end), start.max(left).max(right).max(end))
+        };
+        let (min, max) = curves
+            .iter()
+            .map(bounds)
+            .fold((Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)), |(min, max), (lo, hi)| {
+                (min.min(lo), max.max(hi))
+            });
+        let min = if curves.is_empty() { Vec2::zero() } else { min };
+
+        let cell_size = Self::CELL_SIZE;
+        let size = ((max - min) / cell_size).max(Vec2::zero());
+        let (width, height) = (size.x as usize + 1, size.y as usize + 1);
+        let mut grid = Self { min, cell_size, width, height, cells: vec![vec![]; width * height] };
+
+        for (i, curve) in curves.iter().enumerate() {
+            let (lo, hi) = bounds(curve);
+            let ((x0, y0), (x1, y1)) = (grid.cell_of(lo), grid.cell_of(hi));
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    grid.cells[y * width + x].push(i);
+                }
+            }
+        }
+
+        grid
+    }
+
+    fn cell_of(&self, p: Vec2) -> (usize, usize) {
+        let Vec2 { x, y } = ((p - self.min) / self.cell_size).max(Vec2::zero());
+        ((x as usize).min(self.width - 1), (y as usize).min(self.height - 1))
+    }
+
+    /// The curves in the squares exactly `ring` squares away from `center`.
+    fn ring(&self, (cx, cy): (usize, usize), ring: usize) -> impl Iterator<Item = usize> + '_ {
+        let (cx, cy, ring) = (cx as isize, cy as isize, ring as isize);
+        (cy - ring..=cy + ring)
+            .flat_map(move |y| (cx - ring..=cx + ring).map(move |x| (x, y)))
+            .filter(move |&(x, y)| (x - cx).abs() == ring || (y - cy).abs() == ring)
+            .filter(move |&(x, y)| {
+                x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height
+            })
+            .flat_map(move |(x, y)| {
+                self.cells[y as usize * self.width + x as usize].iter().copied()
+            })
+    }
+}
+
+/// Where the +x side's rods are pinned on the `train` mesh, from empties in `train.glb`.
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct ValveGear {
+    pub crank_pin: Vec3,
+    pub crosshead: Vec3,
+    pub cylinder: Vec3,
 }
 
-pub const INDEX_COUNT: usize = 32000;
-pub const VERTEX_COUNT: usize = 16000;
+pub const INDEX_COUNT: usize = 64000;
+pub const VERTEX_COUNT: usize = 32000;
 pub const IMAGE_SIZE: usize = 16 * 16 * 4;
 
 #[repr(C)]
@@ -166,7 +535,8 @@ pub struct ArtData {
     pub indices: [i16; INDEX_COUNT],
     pub art_indices: ArtIndices,
     pub track_indices: (i32, i32),
-    pub track: Track<3>,
+    pub track: TrackCurves,
+    pub valve_gear: Option<ValveGear>,
 }
 
 impl Default for ArtData {
@@ -180,11 +550,43 @@ impl Default for ArtData {
             last_occupied_vert: 0,
             last_occupied_index: 0,
             track_indices: (0, 0),
+            valve_gear: None,
         }
     }
 }
 
+/// Vertices and indices in an `ArtData::block`.
+const BLOCK_SIZE: (u32, u32) = (5 * 4, 5 * 6);
+
+/// The game's two tiles and rod, which go in before the track.
+const GAME_SIZE: (u32, u32) = (2 * 4 + BLOCK_SIZE.0, 2 * 6 + BLOCK_SIZE.1);
+
 impl ArtData {
+    pub fn read(path: &str) -> std::io::Result<Box<ArtData>> {
+        use std::io::Read;
+        const SIZE: usize = std::mem::size_of::<ArtData>();
+
+        let mut data_bytes = Box::new([0; SIZE]);
+        std::fs::File::open(path)?.read_exact(data_bytes.as_mut())?;
+
+        unsafe {
+            unsafe fn transmute_copy_boxed<T, U>(src: &T) -> Box<U> {
+                let src = src as *const T as *const U;
+                let layout = std::alloc::Layout::new::<U>();
+                let dst = std::alloc::alloc(layout) as *mut U;
+
+                if dst.is_null() {
+                    std::alloc::handle_alloc_error(layout)
+                } else {
+                    src.copy_to(dst, 1);
+                    Box::from_raw(dst)
+                }
+            }
+
+            Ok(transmute_copy_boxed::<[u8; SIZE], ArtData>(data_bytes.as_ref()))
+        }
+    }
+
     fn add_vert(&mut self, vert: Vertex) -> i16 {
         let vert_index = self.last_occupied_vert as usize;
         self.vertices[vert_index] = vert;
@@ -197,48 +599,321 @@ impl ArtData {
         self.last_occupied_index += 1;
     }
 
-    fn line(&mut self, from: Vec2, to: Vec2, thickness: f32) {
-        fn vert(pos: Vec2) -> Vertex {
-            Vertex {
-                pos: glam::vec3(pos.x, 0.0, pos.y),
-                norm: Vec3::unit_y(),
-                uv: Vec2::new(2.0, 0.0) / 16.0 + pos.normalize().abs() / 64.0,
+    fn quad(&mut self, corners: [Vec3; 4], norm: Vec3, uv: Vec2) {
+        let [a, b, c, d] = corners.map(|pos| self.add_vert(Vertex { pos, norm, uv }));
+        for &index in &[a, b, c, c, d, a] {
+            self.add_index(index);
+        }
+    }
+
+    /// A box around `center` along `axes`, without a bottom.
+    fn block(&mut self, center: Vec3, axes: [Vec3; 3], half: [f32; 3], uv: Vec2) {
+        for &(n, u, v) in &[(0, 1, 2), (1, 2, 0), (2, 0, 1)] {
+            for &sign in &[-1.0, 1.0] {
+                let norm = axes[n] * sign;
+                if norm.y < 0.0 {
+                    continue;
+                }
+
+                let mid = center + norm * half[n];
+                let (u, v) = (axes[u] * half[u], axes[v] * half[v]);
+                self.quad([mid - u - v, mid + u - v, mid + u + v, mid - u + v], norm, uv);
             }
         }
+    }
 
-        let normal = (from - to).normalize().perp();
-        let left_from = self.add_vert(vert(from - normal * thickness / 2.0));
-        let right_from = self.add_vert(vert(from + normal * thickness / 2.0));
-        let left_to = self.add_vert(vert(to - normal * thickness / 2.0));
-        let right_to = self.add_vert(vert(to + normal * thickness / 2.0));
+    /// A square two units across, facing along z. Returns its indices.
+    pub fn make_tile(&mut self, uv: UvMap) -> (i32, i32) {
+        let start: i32 = self.last_occupied_index.try_into().unwrap();
+        let (x, y) = (Vec3::unit_x(), Vec3::unit_y());
+        self.quad([-x - y, x - y, x + y, -x + y], Vec3::unit_z(), uv.origin);
+        (start, 6)
+    }
 
-        for &index in &[left_from, right_from, left_to, left_to, right_to, right_from] {
-            self.add_index(index);
+    /// A unit box reaching along z from the origin, to stretch into rods. Returns its indices.
+    pub fn make_rod(&mut self, uv: UvMap) -> (i32, i32) {
+        let start: i32 = self.last_occupied_index.try_into().unwrap();
+        let axes = [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()];
+        self.block(vec3(0.0, 0.0, 0.5), axes, [0.5; 3], uv.origin);
+        (start, self.last_occupied_index as i32 - start)
+    }
+
+    /// Sweeps `profile`, (sideways, up) pairs, along `path`, one flat strip per edge.
+    pub fn extrude(&mut self, path: &[PathPoint], profile: &[Vec2], uv: UvMap) {
+        let mut rings: Vec<Vec<Vec3>> = Vec::with_capacity(path.len());
+        for &PathPoint { pos, forward, .. } in path {
+            let ring = profile
+                .iter()
+                .enumerate()
+                .map(|(i, p)| {
+                    let at = ground(pos + forward.perp() * p.x) + Vec3::unit_y() * p.y;
+                    match rings.last() {
+                        Some(last) if (at - last[i]).dot(ground(forward)) <= 0.0 => last[i],
+                        _ => at,
+                    }
+                })
+                .collect();
+            rings.push(ring);
         }
+
+        let mut across = 0.0;
+        for (i, edge) in profile.windows(2).enumerate() {
+            let (from, to) = (edge[0], edge[1]);
+            let Vec2 { x: out, y: up } = (to - from).perp().normalize();
+            let next_across = across + (to - from).length();
+
+            let mut last = None;
+            for (ring, &PathPoint { forward, dist, .. }) in rings.iter().zip(path) {
+                let norm = ground(forward.perp() * out) + Vec3::unit_y() * up;
+                let from = self.add_vert(Vertex { pos: ring[i], norm, uv: uv.at(across, dist) });
+                let to =
+                    self.add_vert(Vertex { pos: ring[i + 1], norm, uv: uv.at(next_across, dist) });
+
+                if let Some((last_from, last_to)) = last {
+                    for &index in &[last_from, last_to, to, to, from, last_from] {
+                        self.add_index(index);
+                    }
+                }
+                last = Some((from, to));
+            }
+
+            across = next_across;
+        }
+    }
+
+    /// Whether there's room left for this many vertices and indices.
+    pub fn has_room_for(&self, (verts, indices): (u32, u32)) -> bool {
+        (self.last_occupied_vert + verts) as usize <= VERTEX_COUNT
+            && (self.last_occupied_index + indices) as usize <= INDEX_COUNT
     }
 
-    /// Turns the Track data into geometry.
-    pub fn make_track(&mut self) -> Vec<Vec2> {
+    pub fn fits_track(&self, track: &Track, style: &TrackStyle) -> bool {
+        let (verts, indices) = Self::track_size(track, style);
+        self.has_room_for((verts + GAME_SIZE.0, indices + GAME_SIZE.1))
+    }
+
+    /// The most vertices and indices `make_track` could take.
+    pub fn track_size(track: &Track, style: &TrackStyle) -> (u32, u32) {
+        Self::path_size(&track.path(0.0..track.len(), style.tolerance), style)
+    }
+
+    fn path_size(path: &[PathPoint], style: &TrackStyle) -> (u32, u32) {
+        let (rings, length) = (path.len() as u32, path.last().map_or(0.0, |p| p.dist));
+        let strips = 3 + 2 * 3;
+        let sleepers = (length / style.sleeper_spacing + 0.5) as u32;
+        let (verts, indices) = BLOCK_SIZE;
+        (
+            strips * rings * 2 + sleepers * verts,
+            strips * rings.saturating_sub(1) * 6 + sleepers * indices,
+        )
+    }
+
+    /// The most vertices and indices `make_gizmos` could take.
+    pub fn gizmos_size(points: &[BezierPoint]) -> (u32, u32) {
+        let (verts, indices) = BLOCK_SIZE;
+        let blocks = points.len() as u32 * 5;
+        (blocks * verts, blocks * indices)
+    }
+
+    /// Turns the Track data into geometry, if there's room for it.
+    pub fn make_track(&mut self, track: &Track, style: &TrackStyle) -> Result<(), String> {
         let start_index = self.last_occupied_index;
 
-        let rails = (self.track.len() as f32) as usize;
-        let mut points = Vec::with_capacity(rails);
-        let mut before = self.track.point(1.0);
-        for i in 0..=rails {
-            let end_i = if i == rails { 1 } else { i + 1 };
-            let end = self.track.point(end_i as f32 / rails as f32);
-            let normal = (before - end).normalize().perp() * 1.2;
+        let path = track.path(0.0..track.len(), style.tolerance);
+        let (verts, indices) = Self::path_size(&path, style);
+        if !self.has_room_for((verts, indices)) {
+            return Err(format!(
+                "the track needs {} vertices and {} indices, but there's only room for {} and {}",
+                verts,
+                indices,
+                VERTEX_COUNT - self.last_occupied_vert as usize,
+                INDEX_COUNT - self.last_occupied_index as usize
+            ));
+        }
 
-            let middle = self.track.point(i as f32 / rails as f32);
-            self.line(middle + normal, middle - normal, 0.2);
-            before = middle;
-            points.push(middle);
+        let &TrackStyle { rail_height, sleeper_height, .. } = style;
+        let ballast_top = -rail_height - sleeper_height / 2.0;
+        let ballast_bottom = ballast_top - style.ballast_height;
+        let (top, bottom) = (style.ballast_top_width / 2.0, style.ballast_bottom_width / 2.0);
+        self.extrude(
+            &path,
+            &[
+                vec2(-bottom, ballast_bottom),
+                vec2(-top, ballast_top),
+                vec2(top, ballast_top),
+                vec2(bottom, ballast_bottom),
+            ],
+            UvMap::solid(2, 3),
+        );
+
+        let sleeper_middle = -rail_height - sleeper_height / 2.0;
+        let mut next_sleeper = style.sleeper_spacing / 2.0;
+        for pair in path.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            while next_sleeper < b.dist {
+                let t = (next_sleeper - a.dist) / (b.dist - a.dist);
+                let forward = a.forward.lerp(b.forward, t).normalize();
+                self.block(
+                    ground(a.pos.lerp(b.pos, t)) + Vec3::unit_y() * sleeper_middle,
+                    [ground(forward), Vec3::unit_y(), ground(forward.perp())],
+                    [style.sleeper_width / 2.0, sleeper_height / 2.0, style.sleeper_length / 2.0],
+                    UvMap::solid(4, 1).origin,
+                );
+                next_sleeper += style.sleeper_spacing;
+            }
+        }
+
+        let half_width = style.rail_width / 2.0;
+        for &middle in &[-style.gauge / 2.0, style.gauge / 2.0] {
+            let (left, right) = (middle - half_width, middle + half_width);
+            let (side, top) = (UvMap::solid(2, 1), UvMap::solid(2, 0));
+            self.extrude(&path, &[vec2(left, -rail_height), vec2(left, 0.0)], side);
+            self.extrude(&path, &[vec2(left, 0.0), vec2(right, 0.0)], top);
+            self.extrude(&path, &[vec2(right, 0.0), vec2(right, -rail_height)], side);
         }
 
         let start: i32 = start_index.try_into().unwrap();
         let last: i32 = self.last_occupied_index.try_into().unwrap();
         self.track_indices = (start, last - start);
+        Ok(())
+    }
 
-        points
+    /// Markers for the editor's points and handles. Returns their indices.
+    pub fn make_gizmos(&mut self, points: &[BezierPoint], selected: Option<usize>) -> (i32, i32) {
+        let start_index = self.last_occupied_index;
+        let lift = |p: Vec2| ground(p) + Vec3::unit_y() * 0.8;
+
+        for (i, point) in points.iter().enumerate() {
+            let uv =
+                if selected == Some(i) { UvMap::solid(2, 8) } else { UvMap::solid(4, 8) }.origin;
+            let axes = [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()];
+            self.block(lift(point.pos()), axes, [0.9; 3], uv);
+
+            for &handle in &[point.left(), point.right()] {
+                self.block(lift(handle), axes, [0.5; 3], uv);
+
+                let along = handle - point.pos();
+                if along.length() > f32::EPSILON {
+                    let forward = along.normalize();
+                    self.block(
+                        lift(point.pos() + along / 2.0),
+                        [ground(forward), Vec3::unit_y(), ground(forward.perp())],
+                        [along.length() / 2.0, 0.1, 0.1],
+                        uv,
+                    );
+                }
+            }
+        }
+
+        let start: i32 = start_index.try_into().unwrap();
+        let last: i32 = self.last_occupied_index.try_into().unwrap();
+        (start, last - start)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PathPoint {
+    pub pos: Vec2,
+    pub forward: Vec2,
+    pub dist: f32,
+}
+impl PathPoint {
+    fn along(points: &[Vec2]) -> Vec<Self> {
+        let last = points.len() - 1;
+        let looped = points[0].distance(points[last]) < LOOP_GAP;
+
+        let mut dist = 0.0;
+        (0..=last)
+            .map(|i| {
+                let before = match i {
+                    0 if looped => points[last - 1],
+                    0 => points[0],
+                    _ => points[i - 1],
+                };
+                let after = match i {
+                    _ if i == last && looped => points[1],
+                    _ if i == last => points[last],
+                    _ => points[i + 1],
+                };
+                if i > 0 {
+                    dist += points[i].distance(points[i - 1]);
+                }
+
+                Self { pos: points[i], forward: (after - before).normalize(), dist }
+            })
+            .collect()
+    }
+
+    fn clip(path: &[Self], Range { start, end }: Range<f32>) -> Vec<Self> {
+        let at = |dist: f32| {
+            let i = path.iter().rposition(|p| p.dist <= dist).unwrap_or(0).min(path.len() - 2);
+            let (a, b) = (path[i], path[i + 1]);
+            let t = ((dist - a.dist) / (b.dist - a.dist)).clamp(0.0, 1.0);
+            Self {
+                pos: a.pos.lerp(b.pos, t),
+                forward: a.forward.lerp(b.forward, t).normalize(),
+                dist: a.dist + (b.dist - a.dist) * t,
+            }
+        };
+
+        let mut clipped = vec![at(start)];
+        clipped.extend(path.iter().filter(|p| p.dist > start + 0.001 && p.dist < end - 0.001));
+        clipped.push(at(end));
+        clipped
+    }
+}
+
+/// Texture coordinates for `ArtData::extrude`, moving by `scale` per unit across and along.
+#[derive(Debug, Clone, Copy)]
+pub struct UvMap {
+    pub origin: Vec2,
+    pub scale: Vec2,
+}
+impl UvMap {
+    /// One pixel of `ArtData::image` all over.
+    pub fn solid(x: u8, y: u8) -> Self {
+        Self { origin: vec2(x as f32 + 0.5, y as f32 + 0.5) / 16.0, scale: Vec2::zero() }
+    }
+
+    fn at(self, across: f32, along: f32) -> Vec2 {
+        self.origin + self.scale * vec2(across, along)
     }
 }
+
+/// Dimensions for `ArtData::make_track`, with the rail tops at ground level.
+#[derive(Debug, Clone, Copy)]
+pub struct TrackStyle {
+    pub gauge: f32,
+    pub rail_width: f32,
+    pub rail_height: f32,
+    pub sleeper_spacing: f32,
+    pub sleeper_length: f32,
+    pub sleeper_width: f32,
+    pub sleeper_height: f32,
+    pub ballast_top_width: f32,
+    pub ballast_bottom_width: f32,
+    pub ballast_height: f32,
+    pub tolerance: f32,
+}
+
+impl Default for TrackStyle {
+    fn default() -> Self {
+        Self {
+            gauge: 2.8,
+            rail_width: 0.16,
+            rail_height: 0.3,
+            sleeper_spacing: 1.4,
+            sleeper_length: 3.8,
+            sleeper_width: 0.45,
+            sleeper_height: 0.2,
+            ballast_top_width: 4.6,
+            ballast_bottom_width: 7.0,
+            ballast_height: 0.6,
+            tolerance: 0.02,
+        }
+    }
+}
+
+fn ground(Vec2 { x, y }: Vec2) -> Vec3 {
+    vec3(x, 0.0, y)
+}
@@ -0,0 +1,143 @@
+use super::cars::Cars;
+use train::art::Track;
+
+/// The fastest anything's allowed along the track, even dead straight, in metres a second.
+const LINE_SPEED: f32 = 30.0;
+
+/// How much sideways acceleration a curve's speed limit allows for, in metres a second, a second.
+const MAX_LATERAL: f32 = 1.2;
+
+/// The deceleration the autopilot plans its slowing down around, in metres a second, a second.
+/// It's kept gentle so there's braking to spare if it falls behind.
+const PLANNED_BRAKING: f32 = 0.5;
+
+/// How hard the autopilot accelerates, or brakes, at most.
+const MAX_ACCELERATION: f32 = 0.8;
+
+/// How quickly what the autopilot asks for can change, in metres a second, a second, a second.
+/// Without a limit, every change of plan comes as a lurch.
+const MAX_JERK: f32 = 0.6;
+
+/// How much acceleration the autopilot asks for with every metre a second it's off the speed
+/// it's after.
+const GAIN: f32 = 0.5;
+
+/// How near the stopping point, in metres, the train counts as stopped at it.
+const STOP_TOLERANCE: f32 = 0.05;
+
+/// A stretch of track, from `start` to `end` along it, that's not to be run over `speed`.
+#[derive(Debug, Clone, Copy)]
+pub struct SpeedLimit {
+    pub start: f32,
+    pub end: f32,
+    pub speed: f32,
+}
+
+impl SpeedLimit {
+    /// A limit for each curve of `track`, slow enough to take its tightest
+    /// bit without the cars being thrown outwards too hard.
+    pub fn along(track: &Track) -> Vec<SpeedLimit> {
+        let mut start = 0.0;
+        track
+            .curves()
+            .iter()
+            .map(|curve| {
+                let sharpest =
+                    (0..=16).map(|n| curve.curvature(n as f32 / 16.0).abs()).fold(0.0, f32::max);
+                let speed = (MAX_LATERAL / sharpest.max(f32::EPSILON)).sqrt().min(LINE_SPEED);
+                let limit = SpeedLimit { start, end: start + curve.len(), speed };
+                start = limit.end;
+                limit
+            })
+            .collect()
+    }
+}
+
+/// Drives a train on its own: holding a speed, keeping to the speed limits,
+/// and stopping at a spot along the track if it's been given one.
+#[derive(Debug, Clone)]
+pub struct Autopilot {
+    /// The speed to hold where the limits allow it, in metres a second.
+    pub cruise: f32,
+    /// Where along the track to bring the leading end of the train to a stop.
+    pub stop_at: Option<f32>,
+    /// The acceleration being asked for, which is only let change so quickly.
+    demand: f32,
+}
+
+impl Autopilot {
+    pub fn new(cruise: f32) -> Self {
+        Autopilot { cruise, stop_at: None, demand: 0.0 }
+    }
+
+    /// An autopilot that's after as much speed as the track allows.
+    pub fn line_speed() -> Self {
+        Self::new(LINE_SPEED)
+    }
+
+    /// Works the throttle and brake of `train` for the next `dt` seconds. On a loop
+    /// `loop_len` long, the limits and stopping point come round again and again.
+    pub fn drive(
+        &mut self,
+        dt: f32,
+        train: &mut Cars,
+        limits: &[SpeedLimit],
+        loop_len: Option<f32>,
+    ) {
+        // Everything's measured the way the train's being driven, from the end that's leading.
+        let direction = if train.reversed { -1.0 } else { 1.0 };
+        let (front, back) = train.ends();
+        let lead = if train.reversed { back } else { front };
+        let length = front - back;
+        let speed = train.speed() * direction;
+        // How far ahead `at` is, or behind if it's negative, no further back than `behind`.
+        let ahead = |at: f32, behind: f32| {
+            let ahead = (at - lead) * direction;
+            match loop_len {
+                Some(len) if ahead.rem_euclid(len) > len - behind => ahead.rem_euclid(len) - len,
+                Some(len) => ahead.rem_euclid(len),
+                None => ahead,
+            }
+        };
+
+        // The fastest the train can go now and still be slow enough for everything
+        // ahead of it by the time it gets there.
+        let mut target = self.cruise;
+        for limit in limits {
+            let near = if train.reversed { limit.end } else { limit.start };
+            let stretch = limit.end - limit.start;
+            let to = ahead(near, stretch + length);
+            if to > 0.0 {
+                target = target.min((limit.speed.powi(2) + 2.0 * PLANNED_BRAKING * to).sqrt());
+            } else if to > -(stretch + length) {
+                // Some of the train is on it, until the back end's off.
+                target = target.min(limit.speed);
+            }
+        }
+        let to_stop = self.stop_at.map(|at| ahead(at, length));
+        let stopping = to_stop.map_or(f32::MAX, |to| (2.0 * PLANNED_BRAKING * to.max(0.0)).sqrt());
+
+        let wanted = match to_stop {
+            Some(to) if to < STOP_TOLERANCE && speed.abs() < 0.1 => -MAX_ACCELERATION,
+            // Braking for the stop, it slows down just enough to come to a halt right on it.
+            Some(to) if to > STOP_TOLERANCE && stopping < target && speed > stopping - 0.5 => {
+                -speed.max(0.0).powi(2) / (2.0 * to) + GAIN * (stopping - speed)
+            }
+            _ => GAIN * (target.min(stopping) - speed),
+        };
+        let wanted = wanted.clamp(-MAX_ACCELERATION, MAX_ACCELERATION);
+        let step = MAX_JERK * dt;
+        self.demand += (wanted - self.demand).clamp(-step, step);
+
+        // How hard to push or pull the train along to get that acceleration,
+        // on top of whatever's holding it back already.
+        let force = train.mass() * self.demand + train.resistance() * speed.signum();
+        if force > 0.0 {
+            train.throttle = (force / train.pulling_force().max(f32::EPSILON)).min(1.0);
+            train.brake = 0.0;
+        } else {
+            train.throttle = 0.0;
+            train.brake = (-force / train.braking_force().max(f32::EPSILON)).min(1.0);
+        }
+    }
+}
@@ -1,99 +1,793 @@
-use super::{ground_vec2, Art, Rot};
+use super::{autopilot::Autopilot, ground_vec2, Art, Rot};
 use glam::{vec3, Vec2, Vec3, Mat4};
+use std::collections::HashMap;
+
+pub use shells::Shells;
+
+/// Shells fired from the guns.
+mod shells;
+/// Guns aiming at the mouse.
+mod turret;
+/// The rods on a locomotive that turn its driving wheels.
+mod valve_gear;
+/// Cars that have come off the track.
+mod wreck;
+
+/// In m/s².
+const GRAVITY: f32 = 9.81;
+
+/// How far the wheels on one side lead the other's.
+const QUARTERING: f32 = std::f32::consts::FRAC_PI_2;
+
+/// The fastest trains can meet at and couple instead of crashing, in m/s.
+const MAX_COUPLING_SPEED: f32 = 1.5;
+
+const COUPLING_GAP: f32 = 2.109;
 
 pub struct Cars {
     cars: Vec<Car>,
-    track_travelled: f32,
+    /// From 0 to 1.
+    pub throttle: f32,
+    /// From 0 to 1.
+    pub brake: f32,
+    pub reversed: bool,
+    /// 1 if the train was last going forwards, -1 if backwards.
+    facing: f32,
+    pub autopilot: Option<Autopilot>,
 }
 
-impl Default for Cars {
-    fn default() -> Self {
-        Cars {
-            cars: vec![
-                Car {
-                    art: Art::Train,
-                    length: 8.0,
-                    axles: [
-                        Some(Axle { wheel_radius: 1.5, offset: 2.0 }),
-                        Some(Axle { wheel_radius: 1.1, offset: 6.0 }),
-                    ],
-                    ..Default::default()
-                },
-                Car {
-                    art: Art::Cart,
-                    length: 4.76,
-                    axles: [Some(Axle { wheel_radius: 1.1, offset: 3.0 }), None],
-                    gun: Some(Gun { offset: -1.205 }),
+/// The car types, consists and starting trains in `consists.txt`.
+pub struct Roster<'a> {
+    types: HashMap<&'a str, Car>,
+    consists: Vec<(&'a str, Vec<&'a str>)>,
+    /// Each train's consist, where it starts, whether it's reversed and what drives it.
+    trains: Vec<(&'a str, f32, bool, Option<Autopilot>)>,
+}
+
+impl<'a> Roster<'a> {
+    pub fn parse(text: &'a str) -> Result<Self, String> {
+        let mut types: HashMap<&str, Car> = HashMap::new();
+        let mut consists: Vec<(&str, Vec<&str>)> = vec![];
+        let mut trains: Vec<(&str, f32, bool, Option<Autopilot>)> = vec![];
+        enum Section<'a> {
+            None,
+            Car(&'a str, Car, bool),
+            Consist,
+        }
+        let mut section = Section::None;
+
+        fn finish<'a>(section: Section<'a>, types: &mut HashMap<&'a str, Car>) {
+            if let Section::Car(name, mut car, has_couplers) = section {
+                if !has_couplers {
+                    car.couplers = (-COUPLING_GAP / 2.0, car.length + COUPLING_GAP / 2.0);
+                }
+                types.insert(name, car);
+            }
+        }
+
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            let mut words = line.split('#').next().unwrap().split_whitespace();
+            let keyword = match words.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let words: Vec<&str> = words.collect();
+            let numbers_after = |skip: usize| -> Result<Vec<f32>, String> {
+                let parse =
+                    |w: &&str| w.parse().map_err(|_| error(format!("{} isn't a number", w)));
+                words.iter().skip(skip).map(parse).collect()
+            };
+            let exactly = |n: usize| -> Result<Vec<f32>, String> {
+                let numbers = numbers_after(0)?;
+                if numbers.len() == n {
+                    Ok(numbers)
+                } else {
+                    Err(error(format!("{} takes {} numbers", keyword, n)))
+                }
+            };
+
+            match (keyword, &mut section) {
+                ("train", _) => {
+                    let usage = || {
+                        let usage = "train takes a consist and where it starts, \
+                                     then maybe reversed, cruise <speed> and stop <distance>";
+                        error(usage.to_string())
+                    };
+                    let consist = words.first().ok_or_else(usage)?;
+                    let at = words.get(1).and_then(|w| w.parse().ok()).ok_or_else(usage)?;
+                    let (mut reversed, mut autopilot) = (false, None);
+
+                    let mut rest = words.iter().skip(2);
+                    while let Some(&word) = rest.next() {
+                        let mut number =
+                            || rest.next().and_then(|w| w.parse().ok()).ok_or_else(usage);
+                        match word {
+                            "reversed" => reversed = true,
+                            "cruise" => {
+                                autopilot.get_or_insert_with(Autopilot::line_speed).cruise =
+                                    number()?
+                            }
+                            "stop" => {
+                                autopilot.get_or_insert_with(Autopilot::line_speed).stop_at =
+                                    Some(number()?)
+                            }
+                            _ => return Err(usage()),
+                        }
+                    }
+                    trains.push((consist, at, reversed, autopilot));
+                }
+                ("car", _) | ("consist", _) => {
+                    let name =
+                        *words.first().ok_or_else(|| error(format!("{} needs a name", keyword)))?;
+                    let next = match keyword {
+                        "car" => Section::Car(name, Car::default(), false),
+                        _ => {
+                            consists.push((name, vec![]));
+                            Section::Consist
+                        }
+                    };
+                    finish(std::mem::replace(&mut section, next), &mut types);
+                }
+                (_, Section::Consist) => {
+                    let (_, cars) = consists.last_mut().unwrap();
+                    cars.push(keyword);
+                    cars.extend(&words);
+                }
+                (_, Section::Car(_, car, has_couplers)) => match keyword {
+                    "art" => {
+                        let name = words.first().copied().unwrap_or_default();
+                        car.art = Art::from_name(name)
+                            .ok_or_else(|| error(format!("there's no art called {:?}", name)))?;
+                    }
+                    "length" => car.length = exactly(1)?[0],
+                    "mass" => car.mass = exactly(1)?[0],
+                    "centre-of-mass" => car.centre_height = exactly(1)?[0],
+                    "brake" => car.brake_force = exactly(1)?[0],
+                    "gun" => {
+                        let usage = || {
+                            let usage = "gun takes an offset, then maybe traverse <degrees a \
+                                         second>, arc <degrees> and reload <seconds>";
+                            error(usage.to_string())
+                        };
+                        let offset =
+                            words.first().and_then(|w| w.parse().ok()).ok_or_else(usage)?;
+                        let mut gun = Gun { offset, ..Gun::default() };
+
+                        let mut rest = words.iter().skip(1);
+                        while let Some(&word) = rest.next() {
+                            let number: f32 =
+                                rest.next().and_then(|w| w.parse().ok()).ok_or_else(usage)?;
+                            match word {
+                                "traverse" => gun.traverse = number.to_radians(),
+                                "arc" => gun.arc = number.to_radians(),
+                                "reload" => gun.reload = number,
+                                _ => return Err(usage()),
+                            }
+                        }
+                        car.guns.push(gun);
+                    }
+                    "pivots" => {
+                        let n = exactly(2)?;
+                        car.pivots = (n[0], n[1]);
+                    }
+                    "couplers" => {
+                        let n = exactly(2)?;
+                        car.couplers = (n[0], n[1]);
+                        *has_couplers = true;
+                    }
+                    "traction" => {
+                        let n = exactly(2)?;
+                        car.traction = Some(Traction { max_force: n[0], max_power: n[1] });
+                    }
+                    "davis" => {
+                        let n = exactly(3)?;
+                        car.davis = Davis { a: n[0], b: n[1], c: n[2] };
+                    }
+                    "axle" => {
+                        let on_bogie = words.last() == Some(&"bogie");
+                        let words = &words[..words.len() - on_bogie as usize];
+                        let n: Result<Vec<f32>, _> = words.iter().map(|w| w.parse()).collect();
+                        match n.as_deref() {
+                            Ok(&[offset, wheel_radius]) => {
+                                car.axles.push(Axle { offset, wheel_radius, on_bogie })
+                            }
+                            _ => {
+                                let usage = "axle takes an offset, a wheel radius and maybe bogie";
+                                return Err(error(usage.to_string()));
+                            }
+                        }
+                    }
+                    other => return Err(error(format!("cars don't have a {}", other))),
                 },
-            ],
-            track_travelled: 0.0,
+                (other, Section::None) => {
+                    return Err(error(format!("{} comes before any car or consist", other)))
+                }
+            }
+        }
+        finish(section, &mut types);
+
+        Ok(Roster { types, consists, trains })
+    }
+
+    /// The roster's trains, or just the first consist if it lists none.
+    pub fn trains(&self) -> Result<Vec<Cars>, String> {
+        match self.consists.first() {
+            Some((first, _)) if self.trains.is_empty() => Ok(vec![self.build(first, 0.0, false)?]),
+            _ => self
+                .trains
+                .iter()
+                .map(|(name, at, reversed, autopilot)| {
+                    let mut train = self.build(name, *at, *reversed)?;
+                    train.autopilot = autopilot.clone();
+                    Ok(train)
+                })
+                .collect(),
+        }
+    }
+
+    /// A train of consist `name` with its front, or its back if `reversed`, `at` along the track.
+    pub fn build(&self, name: &str, at: f32, reversed: bool) -> Result<Cars, String> {
+        let (_, names) = self
+            .consists
+            .iter()
+            .find(|(n, _)| *n == name)
+            .ok_or_else(|| format!("there's no consist called {}", name))?;
+        let cars = names
+            .iter()
+            .map(|car| {
+                self.types.get(car).cloned().ok_or_else(|| {
+                    format!("{} lists a car called {}, but there isn't one", name, car)
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Cars::new(cars, at, reversed))
+    }
+}
+
+impl Cars {
+    /// Lines `cars` up at rest with their couplers just touching, placed like `Roster::build`.
+    fn new(mut cars: Vec<Car>, at: f32, reversed: bool) -> Self {
+        let mut coupler: Option<f32> = None;
+        for car in &mut cars {
+            car.travelled = coupler.map_or(at, |at| at + car.couplers.0);
+            car.last_travelled = car.travelled;
+            coupler = Some(car.travelled - car.couplers.1);
+        }
+
+        let mut train =
+            Cars { cars, throttle: 0.0, brake: 0.0, reversed, facing: 1.0, autopilot: None };
+        if reversed {
+            let (_, back) = train.ends();
+            for car in &mut train.cars {
+                car.travelled += at - back;
+                car.last_travelled = car.travelled;
+            }
+            train.facing = -1.0;
+        }
+        train
+    }
+
+    /// Moves the train along by `dt` seconds. `grade` is the track's slope at a distance.
+    pub fn step(&mut self, dt: f32, grade: impl Fn(f32) -> f32) {
+        let direction = if self.reversed { -1.0 } else { 1.0 };
+        let mut forces: Vec<f32> = self
+            .cars
+            .iter()
+            .map(|car| {
+                if car.body.is_some() {
+                    return 0.0;
+                }
+                let pull = car.traction.as_ref().map_or(0.0, |t| t.effort(car.speed));
+                let middle = car.travelled - car.length / 2.0;
+                pull * self.throttle * direction - car.mass * GRAVITY * grade(middle).atan().sin()
+            })
+            .collect();
+
+        for i in 0..self.cars.len().saturating_sub(1) {
+            let (front, back) = (&self.cars[i], &self.cars[i + 1]);
+            if front.body.is_some() || back.body.is_some() {
+                continue;
+            }
+            let gap = (front.travelled - front.couplers.1) - (back.travelled - back.couplers.0);
+            let tension = front.coupler.tension(gap, front.speed - back.speed);
+            forces[i] -= tension;
+            forces[i + 1] += tension;
+        }
+
+        for (car, force) in self.cars.iter_mut().zip(forces) {
+            car.last_travelled = car.travelled;
+            if car.body.is_some() {
+                continue;
+            }
+
+            let resistance = car.davis.at(car.speed) + car.brake_force * self.brake;
+            let unresisted = car.speed + force / car.mass * dt;
+            let slowed = (unresisted.abs() - resistance / car.mass * dt).max(0.0);
+            car.speed = slowed * unresisted.signum();
+            car.travelled += car.speed * dt;
+        }
+
+        // So the camera doesn't swing round at every nudge.
+        let speed = self.speed();
+        if speed.abs() > 0.5 {
+            self.facing = speed.signum();
+        }
+    }
+
+    /// Uncouples the cars from `at` on, as a train of their own.
+    pub fn split(&mut self, at: usize) -> Cars {
+        let cars = self.cars.split_off(at);
+        Cars {
+            cars,
+            throttle: 0.0,
+            brake: self.brake,
+            reversed: self.reversed,
+            facing: self.facing,
+            autopilot: None,
+        }
+    }
+
+    pub fn couple(&mut self, behind: Cars) {
+        let pulls = |train: &Cars| train.cars.iter().any(|car| car.traction.is_some());
+        if !pulls(self) && pulls(&behind) {
+            self.throttle = behind.throttle;
+            self.brake = behind.brake;
+            self.reversed = behind.reversed;
+            self.autopilot = behind.autopilot;
+        }
+        self.cars.extend(behind.cars);
+    }
+
+    /// Where the coupler faces at the front and back of the train are.
+    pub fn ends(&self) -> (f32, f32) {
+        let (first, last) = (&self.cars[0], &self.cars[self.cars.len() - 1]);
+        (first.travelled - first.couplers.0, last.travelled - last.couplers.1)
+    }
+
+    pub fn speed(&self) -> f32 {
+        let mass: f32 = self.cars.iter().map(|car| car.mass).sum();
+        self.cars.iter().map(|car| car.speed * car.mass).sum::<f32>() / mass
+    }
+
+    /// In kilograms.
+    pub fn mass(&self) -> f32 {
+        self.cars.iter().map(|car| car.mass).sum()
+    }
+
+    /// In newtons, at the speed they're going.
+    pub fn pulling_force(&self) -> f32 {
+        let effort = |car: &Car| car.traction.as_ref().map_or(0.0, |t| t.effort(car.speed));
+        self.cars.iter().map(effort).sum()
+    }
+
+    /// In newtons, with the brakes all the way on.
+    pub fn braking_force(&self) -> f32 {
+        self.cars.iter().map(|car| car.brake_force).sum()
+    }
+
+    /// In newtons, from the Davis equation alone.
+    pub fn resistance(&self) -> f32 {
+        self.cars.iter().map(|car| car.davis.at(car.speed)).sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Collision {
+    RearEnd { behind: usize, ahead: usize, speed: f32 },
+    HeadOn { trains: (usize, usize), speed: f32 },
+}
+
+impl std::fmt::Display for Collision {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Collision::RearEnd { behind, ahead, speed } => write!(
+                f,
+                "train {} ran into the back of train {} at {:.1} metres a second",
+                behind, ahead, speed
+            ),
+            Collision::HeadOn { trains: (a, b), speed } => {
+                write!(f, "trains {} and {} met head on at {:.1} metres a second", a, b, speed)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Event {
+    Crashed(Collision),
+    Coupled { behind: usize, ahead: usize },
+    Derailed { train: usize, car: usize },
+    Hit { train: usize, car: usize },
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Event::Crashed(collision) => collision.fmt(f),
+            Event::Coupled { behind, ahead } => {
+                write!(f, "train {} coupled on behind train {}", behind, ahead)
+            }
+            Event::Derailed { train, car } => {
+                write!(f, "car {} of train {} came off the track", car, train)
+            }
+            Event::Hit { train, car } => write!(f, "car {} of train {} was hit", car, train),
+        }
+    }
+}
+
+impl Event {
+    /// Which of the alert tiles lights up for it.
+    pub fn alert(&self) -> usize {
+        match self {
+            Event::Crashed(_) => 0,
+            Event::Coupled { .. } => 1,
+            Event::Derailed { .. } => 2,
+            Event::Hit { .. } => 3,
+        }
+    }
+}
+
+impl Collision {
+    pub fn speed(&self) -> f32 {
+        match *self {
+            Collision::RearEnd { speed, .. } | Collision::HeadOn { speed, .. } => speed,
+        }
+    }
+
+    /// Pairs of `trains` overlapping, the first's front past the second's back.
+    pub fn touching(trains: &[Cars], loop_len: Option<f32>) -> Vec<(usize, usize)> {
+        let mut touching = vec![];
+        for (a, train_a) in trains.iter().enumerate() {
+            for (b, train_b) in trains.iter().enumerate().skip(a + 1) {
+                let ((a_front, a_back), (b_front, b_back)) = (train_a.ends(), train_b.ends());
+                let past = a_front - b_back;
+                let past = loop_len.map_or(past, |len| past.rem_euclid(len));
+
+                if past >= 0.0 && past < b_front - b_back {
+                    touching.push((a, b));
+                } else if past >= 0.0 && past < (b_front - b_back) + (a_front - a_back) {
+                    touching.push((b, a));
+                }
+            }
+        }
+        touching
+    }
+
+    pub fn between(trains: &[Cars], (a, b): (usize, usize)) -> Self {
+        let (a_speed, b_speed) = (trains[a].speed(), trains[b].speed());
+        let speed = a_speed - b_speed;
+        if a_speed > 0.0 && b_speed < 0.0 {
+            Collision::HeadOn { trains: (a, b), speed }
+        } else if speed >= 0.0 {
+            Collision::RearEnd { behind: a, ahead: b, speed }
+        } else {
+            Collision::RearEnd { behind: b, ahead: a, speed: -speed }
         }
     }
 }
 
 impl super::Stage {
-    pub fn draw_train(&mut self, rq: &mut super::RenderQueue) {
-        self.train.track_travelled += 0.8;
-        let dist = self.train.track_travelled;
-        let mut length_so_far = 0.0;
+    /// Couples trains that meet gently enough, and queues the rest up as crashes.
+    pub fn handle_contacts(&mut self) {
+        let touching = self.touching_trains();
+        for &(a, b) in &touching {
+            if self.touching.iter().any(|&pair| pair == (a, b) || pair == (b, a)) {
+                continue;
+            }
 
-        for car in &self.train.cars {
-            let front = self.track_point(dist - length_so_far);
-            length_so_far += car.length;
-            let back = self.track_point(dist - length_so_far);
+            let collision = Collision::between(&self.trains, (a, b));
+            if collision.speed() <= MAX_COUPLING_SPEED {
+                self.couple(a, b);
+                // Coupling renumbers the trains.
+                self.touching = self.touching_trains();
+                return;
+            }
+            self.events.push(Event::Crashed(collision));
+        }
+        self.touching = touching;
+    }
 
-            let to_back = Rot::from_vec2(back - front);
-            rq.draw(car.art, front, to_back);
+    /// Lights a tile at the top left for each sort of event that's happened lately.
+    pub fn draw_alerts(&self, rq: &mut super::RenderQueue) {
+        const SIZE: f32 = 12.0;
+        let square = Vec2::splat(SIZE / 2.0 * 0.8).extend(1.0);
+        for (n, &left) in self.alerts.iter().enumerate() {
+            let tile = if left > 0.0 { super::Tile::Lit } else { super::Tile::Unlit };
+            let at = vec3(SIZE * (2.0 + n as f32 * 1.5), SIZE * 2.0, 0.0);
+            rq.draw_tile(tile, Mat4::from_translation(at) * Mat4::from_scale(square));
+        }
+    }
+
+    fn touching_trains(&self) -> Vec<(usize, usize)> {
+        let loop_len = if self.track_curves.is_loop() { Some(self.track_len()) } else { None };
+        Collision::touching(&self.trains, loop_len)
+    }
 
-            for &Axle { offset, wheel_radius } in car.axles.iter().filter_map(|x| x.as_ref()) {
-                use std::f32::consts::{FRAC_PI_2, PI, TAU};
+    fn couple(&mut self, a: usize, b: usize) {
+        self.events.push(Event::Coupled { behind: a, ahead: b });
+        let behind = self.trains.remove(a);
+        let b = if b > a { b - 1 } else { b };
+        self.trains[b].couple(behind);
 
-                for &(pitch, out_dir) in &[(0.0, -1.0), (PI, 1.0)] {
-                    let out = to_back.vec2().perp() * out_dir * 1.4;
-                    let Vec2 { x, y: z } = front + to_back.vec2() * offset + out;
+        let followed = match self.followed {
+            f if f == a => b,
+            f if f > a => f - 1,
+            f => f,
+        };
+        if followed == b {
+            self.follow(b);
+        } else {
+            self.followed = followed;
+        }
+    }
+
+    /// Stops trains dead at the ends of a track that isn't a loop.
+    pub fn stop_at_ends(&mut self) {
+        if self.track_curves.is_loop() {
+            return;
+        }
+        let len = self.track_len();
+        for train in &mut self.trains {
+            let (front, back) = train.ends();
+            let over = (front - len).max(0.0) + back.min(0.0);
+            if over != 0.0 {
+                for car in &mut train.cars {
+                    car.travelled -= over;
+                    car.last_travelled -= over;
+                    car.speed = 0.0;
+                }
+            }
+        }
+    }
+
+    pub fn drop_last_car(&mut self) {
+        let train = &mut self.trains[self.followed];
+        if train.cars.len() > 1 {
+            let last = train.split(train.cars.len() - 1);
+            self.trains.push(last);
+            self.touching = self.touching_trains();
+        }
+    }
+
+    /// Draws the trains `blend` of the way from the step before last to the last one.
+    pub fn draw_trains(&self, rq: &mut super::RenderQueue, blend: f32) {
+        use std::f32::consts::PI;
+        for car in self.trains.iter().flat_map(|train| &train.cars) {
+            let dist = car.travelled(blend);
+            let body = self.body_matrix(car, dist);
+            rq.draw_mat4(car.art, body);
+
+            for &axle in &car.axles {
+                let Axle { offset, wheel_radius, on_bogie } = axle;
+
+                let axle = if on_bogie && car.body.is_none() {
+                    let dist = dist - offset;
+                    placed(self.track_point(dist), Rot::from_vec2(-self.track_heading(dist)))
+                } else {
+                    body * Mat4::from_translation(vec3(0.0, 0.0, offset))
+                };
+
+                let turned = dist / wheel_radius;
+                for &(pitch, out_dir, lead) in &[(0.0, -1.0, 0.0), (PI, 1.0, QUARTERING)] {
+                    let out = -out_dir * self.track_style.gauge / 2.0;
                     rq.draw_mat4(
                         Art::Wheel,
-                        Mat4::from_translation(vec3(x, wheel_radius, z))
-                            * Mat4::from_rotation_y(pitch + FRAC_PI_2 - to_back.0)
-                            * Mat4::from_rotation_x(dist / TAU * wheel_radius * out_dir - pitch / 2.0)
+                        axle * Mat4::from_translation(vec3(out, wheel_radius, 0.0))
+                            * Mat4::from_rotation_y(pitch)
+                            * Mat4::from_rotation_x((turned - lead) * out_dir)
                             * Mat4::from_scale(Vec3::splat(wheel_radius)),
                     )
                 }
             }
 
-            if let Some(gun) = &car.gun {
-                rq.draw(Art::Gun, front - to_back.vec2() * gun.offset, to_back);
+            if let (Art::Train, Some(gear)) = (car.art, &self.art_data.valve_gear) {
+                valve_gear::draw(rq, gear, car, body, dist);
             }
 
-            length_so_far += 2.109;
+            for gun in &car.guns {
+                let mount = body * Mat4::from_translation(vec3(0.0, 0.0, -gun.offset));
+                let turret = mount * Mat4::from_rotation_y(gun.yaw);
+                rq.draw_mat4(Art::Gun, turret);
+                if gun.flash > 0.0 {
+                    rq.draw_mat4(Art::Flash, turret * shells::muzzle_flash(gun.flash));
+                }
+            }
         }
+    }
+
+    /// Places the body of `car`, front at the origin and back along +z.
+    fn body_matrix(&self, car: &Car, dist: f32) -> Mat4 {
+        match &car.body {
+            Some(body) => body.matrix(car),
+            None => {
+                let (front, to_back) = self.car_pose(car, dist);
+                placed(front, to_back)
+            }
+        }
+    }
 
-        self.cam_origin = ground_vec2(self.track_point(dist));
+    /// Where the front of `car` is `dist` along the track, and which way its body points back.
+    fn car_pose(&self, car: &Car, dist: f32) -> (Vec2, Rot) {
+        let (front_pivot, back_pivot) = car.pivots;
+        let pivot = self.track_point(dist - front_pivot);
+        let to_back = Rot::from_vec2(self.track_point(dist - back_pivot) - pivot);
+        (pivot - to_back.vec2() * front_pivot, to_back)
+    }
+
+    pub fn follow_train(&mut self, blend: f32) {
+        let train = &self.trains[self.followed];
+        let (first, last) = (&train.cars[0], &train.cars[train.cars.len() - 1]);
+        let front = first.travelled(blend) - first.couplers.0;
+        let back = last.travelled(blend) - last.couplers.1;
+        let (ahead, behind) = if train.facing < 0.0 { (back, front) } else { (front, back) };
+
+        self.cam_origin = ground_vec2(self.track_point(ahead));
         self.cam_offset = {
-            let Vec2 { x, y } = self.track_point(dist - length_so_far - 20.0);
+            let Vec2 { x, y } = self.track_point(behind - train.facing * 20.0);
             vec3(x, 20.0, y) - self.cam_origin
         };
     }
 }
 
+fn placed(at: Vec2, to_back: Rot) -> Mat4 {
+    use std::f32::consts::FRAC_PI_2;
+    Mat4::from_translation(ground_vec2(at)) * Mat4::from_rotation_y(FRAC_PI_2 - to_back.0)
+}
+
+#[derive(Clone)]
 struct Car {
     length: f32,
     art: Art,
-    axles: [Option<Axle>; 2],
-    gun: Option<Gun>,
+    axles: Vec<Axle>,
+    guns: Vec<Gun>,
+    /// How far back from the front the car rests on the track.
+    pivots: (f32, f32),
+    mass: f32,
+    centre_height: f32,
+    traction: Option<Traction>,
+    /// In newtons.
+    brake_force: f32,
+    davis: Davis,
+    /// How far back from the front the coupler faces are.
+    couplers: (f32, f32),
+    coupler: Coupler,
+    travelled: f32,
+    last_travelled: f32,
+    speed: f32,
+    body: Option<Box<wreck::Body>>,
+}
+
+impl Car {
+    fn travelled(&self, blend: f32) -> f32 {
+        self.last_travelled + (self.travelled - self.last_travelled) * blend
+    }
+
+    /// The sideways acceleration the car tips over at, in m/s².
+    fn tipping_point(&self, gauge: f32) -> f32 {
+        GRAVITY * gauge / 2.0 / self.centre_height
+    }
 }
 
 impl Default for Car {
     fn default() -> Self {
-        Car { length: 5.0, art: Art::Cart, axles: [None, None], gun: None }
+        Car {
+            length: 5.0,
+            art: Art::Cart,
+            axles: vec![],
+            guns: vec![],
+            pivots: (1.0, 4.0),
+            mass: 8_000.0,
+            centre_height: 1.8,
+            traction: None,
+            brake_force: 12_000.0,
+            davis: Davis { a: 250.0, b: 10.0, c: 2.0 },
+            couplers: (-COUPLING_GAP / 2.0, 5.0 + COUPLING_GAP / 2.0),
+            coupler: Coupler::default(),
+            travelled: 0.0,
+            last_travelled: 0.0,
+            speed: 0.0,
+            body: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Traction {
+    max_force: f32,
+    max_power: f32,
+}
+
+impl Traction {
+    fn effort(&self, speed: f32) -> f32 {
+        self.max_force.min(self.max_power / speed.abs().max(f32::EPSILON))
+    }
+}
+
+/// The Davis equation's coefficients.
+#[derive(Clone)]
+struct Davis {
+    a: f32,
+    b: f32,
+    c: f32,
+}
+
+impl Davis {
+    fn at(&self, speed: f32) -> f32 {
+        let speed = speed.abs();
+        self.a + self.b * speed + self.c * speed * speed
+    }
+}
+
+/// A coupling between two cars, loose for `slack` metres and springy past that.
+#[derive(Clone)]
+struct Coupler {
+    slack: f32,
+    /// N/m.
+    draft_stiffness: f32,
+    /// N/m.
+    buff_stiffness: f32,
+    /// N/(m/s).
+    damping: f32,
+}
+
+impl Default for Coupler {
+    fn default() -> Self {
+        Coupler {
+            slack: 0.4,
+            draft_stiffness: 400_000.0,
+            buff_stiffness: 800_000.0,
+            damping: 45_000.0,
+        }
+    }
+}
+
+impl Coupler {
+    /// How hard the coupler pulls the cars together, negative when it pushes.
+    fn tension(&self, stretch: f32, stretch_rate: f32) -> f32 {
+        let half = self.slack / 2.0;
+        if stretch > half {
+            (self.draft_stiffness * (stretch - half) + self.damping * stretch_rate).max(0.0)
+        } else if stretch < -half {
+            (self.buff_stiffness * (stretch + half) + self.damping * stretch_rate).min(0.0)
+        } else {
+            0.0
+        }
     }
 }
 
+#[derive(Clone)]
 struct Gun {
     offset: f32,
+    /// In radians a second.
+    traverse: f32,
+    /// How far either way from the front it can turn, in radians.
+    arc: f32,
+    /// Radians from the front, positive to the left.
+    yaw: f32,
+    /// In seconds.
+    reload: f32,
+    loading: f32,
+    flash: f32,
+}
+
+impl Default for Gun {
+    fn default() -> Self {
+        Gun {
+            offset: 0.0,
+            traverse: 45f32.to_radians(),
+            arc: std::f32::consts::PI,
+            yaw: 0.0,
+            reload: 3.0,
+            loading: 0.0,
+            flash: 0.0,
+        }
+    }
 }
 
+#[derive(Clone, Copy)]
 struct Axle {
     offset: f32,
     wheel_radius: f32,
+    on_bogie: bool,
 }
@@ -0,0 +1,211 @@
+//! Shells fired from the guns, and the flashes of them going off.
+use super::{wreck, GRAVITY};
+use glam::{vec3, Mat4, Vec3};
+
+/// The end of the barrel on the gun mesh.
+const MUZZLE: [f32; 3] = [0.0, 5.55, -2.72];
+
+/// In m/s.
+const MUZZLE_SPEED: f32 = 80.0;
+
+const SHELL_MASS: f32 = 15.0;
+
+/// Seconds before a shell can hit anything, so it clears its own car.
+const ARMING: f32 = 0.1;
+
+const MAX_SHELLS: usize = 64;
+
+/// In seconds.
+const FLASH_TIME: f32 = 0.15;
+
+const MUZZLE_FLASH: f32 = 1.2;
+const BURST: f32 = 3.0;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Shell {
+    at: Vec3,
+    last_at: Vec3,
+    velocity: Vec3,
+    age: f32,
+    flying: bool,
+}
+
+/// Pools of shells and flashes, reused rather than allocated.
+pub struct Shells {
+    shells: Vec<Shell>,
+    /// Where, how big, and seconds left.
+    bursts: Vec<(Vec3, f32, f32)>,
+}
+
+impl Default for Shells {
+    fn default() -> Self {
+        Shells {
+            shells: vec![Shell::default(); MAX_SHELLS],
+            bursts: vec![(Vec3::zero(), 0.0, 0.0); MAX_SHELLS],
+        }
+    }
+}
+
+impl Shells {
+    /// Sends a shell off, if there's a free slot.
+    fn fire(&mut self, at: Vec3, velocity: Vec3) -> bool {
+        match self.shells.iter_mut().find(|shell| !shell.flying) {
+            Some(shell) => {
+                *shell = Shell { at, last_at: at, velocity, age: 0.0, flying: true };
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn burst(&mut self, at: Vec3, size: f32) {
+        let slot = self.bursts.iter_mut().min_by(|a, b| a.2.partial_cmp(&b.2).unwrap()).unwrap();
+        *slot = (at, size, FLASH_TIME);
+    }
+}
+
+pub fn muzzle_flash(left: f32) -> Mat4 {
+    Mat4::from_translation(MUZZLE.into())
+        * Mat4::from_scale(Vec3::splat(MUZZLE_FLASH * left / FLASH_TIME))
+}
+
+/// The flatter angle to raise a barrel to for a hit `across` away and `up` higher.
+fn elevation(across: f32, up: f32) -> f32 {
+    let v2 = MUZZLE_SPEED * MUZZLE_SPEED;
+    let reach = v2 * v2 - GRAVITY * (GRAVITY * across * across + 2.0 * up * v2);
+    if reach < 0.0 || across <= 0.0 {
+        return std::f32::consts::FRAC_PI_4;
+    }
+    ((v2 - reach.sqrt()) / (GRAVITY * across)).atan()
+}
+
+impl super::super::Stage {
+    /// Fires every loaded gun on the train being driven, ranged on the mouse.
+    pub fn fire_guns(&mut self) {
+        let target = self.mouse_on_ground;
+        for i in 0..self.trains[self.followed].cars.len() {
+            let car = &self.trains[self.followed].cars[i];
+            if car.body.is_some() {
+                continue;
+            }
+
+            let body = self.body_matrix(car, car.travelled);
+            let heading = self.track_heading(car.travelled - car.length / 2.0);
+            let carried = vec3(heading.x, 0.0, heading.y) * car.speed;
+            for gun in &mut self.trains[self.followed].cars[i].guns {
+                if gun.loading > 0.0 {
+                    continue;
+                }
+
+                let turret = body
+                    * Mat4::from_translation(vec3(0.0, 0.0, -gun.offset))
+                    * Mat4::from_rotation_y(gun.yaw);
+                let muzzle = turret.transform_point3(MUZZLE.into());
+                let forward = turret.transform_vector3(-Vec3::unit_z()).normalize();
+                let to = target - muzzle;
+                let across = vec3(to.x, 0.0, to.z).length();
+                let (sin, cos) = elevation(across, to.y).sin_cos();
+                let velocity = (forward * cos + Vec3::unit_y() * sin) * MUZZLE_SPEED;
+
+                if self.shells.fire(muzzle, velocity + carried) {
+                    gun.loading = gun.reload;
+                    gun.flash = FLASH_TIME;
+                }
+            }
+        }
+    }
+
+    pub fn step_shells(&mut self, dt: f32) {
+        for gun in
+            self.trains.iter_mut().flat_map(|train| &mut train.cars).flat_map(|car| &mut car.guns)
+        {
+            gun.loading = (gun.loading - dt).max(0.0);
+            gun.flash = (gun.flash - dt).max(0.0);
+        }
+        for burst in &mut self.shells.bursts {
+            burst.2 = (burst.2 - dt).max(0.0);
+        }
+
+        for s in 0..self.shells.shells.len() {
+            let mut shell = self.shells.shells[s];
+            if !shell.flying {
+                continue;
+            }
+            shell.last_at = shell.at;
+            shell.velocity.y -= GRAVITY * dt;
+            shell.at += shell.velocity * dt;
+            shell.age += dt;
+
+            if shell.at.y <= 0.0 {
+                let path = shell.at - shell.last_at;
+                let at = super::super::line_plane_intersect(
+                    shell.last_at,
+                    path,
+                    Vec3::zero(),
+                    Vec3::unit_y(),
+                );
+                self.shells.burst(at, BURST);
+                shell.flying = false;
+            } else if shell.age > ARMING {
+                if let Some((t, i)) = self.car_at(shell.at) {
+                    self.hit(t, i, shell.at, shell.velocity * SHELL_MASS, dt);
+                    self.shells.burst(shell.at, BURST);
+                    shell.flying = false;
+                }
+            }
+            self.shells.shells[s] = shell;
+        }
+    }
+
+    /// The train and car whose body is around `at`.
+    fn car_at(&self, at: Vec3) -> Option<(usize, usize)> {
+        let half_width = self.track_style.gauge * wreck::WIDTH / 2.0;
+        self.trains.iter().enumerate().find_map(|(t, train)| {
+            let i = train.cars.iter().position(|car| {
+                let local = self.body_matrix(car, car.travelled).inverse().transform_point3(at);
+                local.x.abs() < half_width
+                    && (0.0..car.centre_height * 2.0).contains(&local.y)
+                    && (0.0..car.length).contains(&local.z)
+            })?;
+            Some((t, i))
+        })
+    }
+
+    fn hit(&mut self, t: usize, i: usize, at: Vec3, momentum: Vec3, dt: f32) {
+        self.events.push(super::Event::Hit { train: t, car: i });
+        let gauge = self.track_style.gauge;
+        let car = &self.trains[t].cars[i];
+        let heading = self.track_heading(car.travelled - car.length / 2.0);
+
+        let car = &mut self.trains[t].cars[i];
+        match car.body.take() {
+            Some(mut body) => {
+                body.push(car, gauge, at, momentum / dt, dt);
+                car.body = Some(body);
+            }
+            None => car.speed += momentum.dot(vec3(heading.x, 0.0, heading.y)) / car.mass,
+        }
+    }
+
+    pub fn draw_shells(&self, rq: &mut super::super::RenderQueue, blend: f32) {
+        for shell in self.shells.shells.iter().filter(|shell| shell.flying) {
+            let at = shell.last_at.lerp(shell.at, blend);
+            let (x, y, z) = shell.velocity.into();
+            let level = (x * x + z * z).sqrt();
+            rq.draw_mat4(
+                super::Art::Shell,
+                Mat4::from_translation(at)
+                    * Mat4::from_rotation_y((-x).atan2(-z))
+                    * Mat4::from_rotation_x(y.atan2(level)),
+            );
+        }
+
+        for &(at, size, left) in self.shells.bursts.iter().filter(|burst| burst.2 > 0.0) {
+            let size = size * left / FLASH_TIME;
+            rq.draw_mat4(
+                super::Art::Flash,
+                Mat4::from_translation(at) * Mat4::from_scale(Vec3::splat(size)),
+            );
+        }
+    }
+}
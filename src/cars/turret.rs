@@ -0,0 +1,45 @@
+//! Guns turning on their mounts to aim at wherever the mouse is pointing on the ground.
+use super::Gun;
+use glam::vec3;
+use std::f32::consts::{PI, TAU};
+
+impl Gun {
+    /// Turns the turret as far towards `wanted` as it can in `dt` seconds, without going
+    /// past the ends of its arc. A turret that goes all the way round takes the short way.
+    fn turn_towards(&mut self, wanted: f32, dt: f32) {
+        let all_round = self.arc >= PI;
+        let wanted = wanted.clamp(-self.arc, self.arc);
+        let change = wanted - self.yaw;
+        let change = if all_round { (change + PI).rem_euclid(TAU) - PI } else { change };
+        let step = self.traverse * dt;
+        self.yaw += change.clamp(-step, step);
+        if all_round {
+            self.yaw = (self.yaw + PI).rem_euclid(TAU) - PI;
+        }
+    }
+}
+
+impl super::super::Stage {
+    /// Turns every gun towards the spot on the ground under the mouse for `dt` seconds,
+    /// each from wherever it's mounted. Guns on cars that have come off the track are
+    /// left pointing wherever they were.
+    pub fn aim_guns(&mut self, dt: f32) {
+        let target = self.mouse_on_ground;
+        for t in 0..self.trains.len() {
+            for i in 0..self.trains[t].cars.len() {
+                let car = &self.trains[t].cars[i];
+                if car.body.is_some() {
+                    continue;
+                }
+
+                // Where the mouse is, seen from the car with its front pointing along -z.
+                let target =
+                    self.body_matrix(car, car.travelled).inverse().transform_point3(target);
+                for gun in &mut self.trains[t].cars[i].guns {
+                    let to = target - vec3(0.0, 0.0, -gun.offset);
+                    gun.turn_towards((-to.x).atan2(-to.z), dt);
+                }
+            }
+        }
+    }
+}
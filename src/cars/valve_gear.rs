@@ -0,0 +1,65 @@
+//! The rods that turn a locomotive's driving wheels: a piston pushing a crosshead
+//! back and forth, a connecting rod from there to the crank pin on the driving wheel,
+//! and side rods from that crank pin to the ones on the wheels coupled to it.
+use super::super::RenderQueue;
+use super::{Axle, Car, QUARTERING};
+use glam::{vec3, Mat4, Quat, Vec3};
+use train::art::ValveGear;
+
+/// How thick each of the rods is, across and up.
+const SIDE_ROD: f32 = 0.16;
+const CONNECTING_ROD: f32 = 0.14;
+const PISTON_ROD: f32 = 0.08;
+
+/// Draws the rods on both sides of `car`, its body placed by `body`, with the driving wheel
+/// turned to match it having gone `dist` along the track. The driving wheel is the first
+/// one held straight by the body, and any others that size held straight are coupled to it.
+pub fn draw(rq: &mut RenderQueue, gear: &ValveGear, car: &Car, body: Mat4, dist: f32) {
+    let driver = match car.axles.iter().find(|axle| !axle.on_bogie) {
+        Some(driver) => *driver,
+        None => return,
+    };
+    let coupled = car
+        .axles
+        .iter()
+        .filter(|axle| !axle.on_bogie && axle.wheel_radius == driver.wheel_radius)
+        .skip(1);
+
+    // The crank pin, from the middle of the driving wheel, with it turned to zero.
+    let axle_middle = |axle: &Axle, x: f32| vec3(x, axle.wheel_radius, axle.offset);
+    let crank = gear.crank_pin - axle_middle(&driver, gear.crank_pin.x);
+    let connecting = gear.crosshead.distance(gear.crank_pin);
+
+    for &(side, lead) in &[(1.0, 0.0), (-1.0, QUARTERING)] {
+        let mirror = vec3(side, 1.0, 1.0);
+        let (crosshead, cylinder) = (gear.crosshead * mirror, gear.cylinder * mirror);
+        let guide = (crosshead - cylinder).normalize();
+        let angle = lead - dist / driver.wheel_radius;
+        let pin = |axle: &Axle| {
+            axle_middle(axle, gear.crank_pin.x * side) + Quat::from_rotation_x(angle) * crank
+        };
+        let rod = |rq: &mut RenderQueue, from: Vec3, to: Vec3, thickness: f32| {
+            let along = to - from;
+            rq.draw_rod(
+                body * Mat4::from_translation(from)
+                    * Mat4::from_rotation_x((-along.y).atan2(along.z))
+                    * Mat4::from_scale(vec3(thickness, thickness, along.length())),
+            );
+        };
+
+        let driving_pin = pin(&driver);
+        for axle in coupled.clone() {
+            rod(rq, driving_pin, pin(axle), SIDE_ROD);
+        }
+
+        // The crosshead's wherever along its line it's the connecting rod's length
+        // from the crank pin, on the cylinder's side of it.
+        let from_pin = crosshead - driving_pin;
+        let b = from_pin.dot(guide);
+        let reach = b * b - from_pin.length_squared() + connecting * connecting;
+        let slide = guide * (-b - reach.max(0.0).sqrt());
+
+        rod(rq, driving_pin, crosshead + slide, CONNECTING_ROD);
+        rod(rq, crosshead + slide, cylinder + slide, PISTON_ROD);
+    }
+}
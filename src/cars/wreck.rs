@@ -0,0 +1,230 @@
+//! Cars that have come off the track, tumbling along the ground as rigid bodies.
+use super::{Car, Rot, GRAVITY};
+use glam::{vec3, Mat4, Quat, Vec2, Vec3};
+
+const COUPLER_HEIGHT: f32 = 1.0;
+
+/// How wide a car's body is, in gauges.
+pub const WIDTH: f32 = 1.2;
+
+/// N/m per kilogram.
+const GROUND_STIFFNESS: f32 = 200.0;
+
+/// N/(m/s) per kilogram.
+const GROUND_DAMPING: f32 = 10.0;
+
+const GROUND_FRICTION: f32 = 0.6;
+
+/// In radians a second.
+const TIPPING_SPIN: f32 = 2.0;
+
+/// Radians a second added per m/s² over the limit.
+const ROLL_RATE: f32 = 0.3;
+
+/// m/s added per m/s² over the limit.
+const THROW: f32 = 0.3;
+
+/// In m/s.
+const HOP: f32 = 2.0;
+
+#[derive(Clone)]
+pub struct Body {
+    centre: Vec3,
+    velocity: Vec3,
+    rotation: Quat,
+    spin: Vec3,
+}
+
+impl Body {
+    fn centre_offset(car: &Car) -> Vec3 {
+        vec3(0.0, car.centre_height, car.length / 2.0)
+    }
+
+    fn inertia(car: &Car, gauge: f32) -> Vec3 {
+        let (w, h, l) = (gauge * WIDTH, car.centre_height * 2.0, car.length);
+        vec3(h * h + l * l, w * w + l * l, w * w + h * h) * car.mass / 12.0
+    }
+
+    /// A body for `car` just as it's thrown `outward`, `excess` over the limit.
+    pub fn derail(
+        car: &Car,
+        front: Vec2,
+        to_back: Rot,
+        heading: Vec2,
+        outward: Vec2,
+        excess: f32,
+    ) -> Self {
+        use std::f32::consts::FRAC_PI_2;
+        let rotation = Quat::from_rotation_y(FRAC_PI_2 - to_back.0);
+        let outward = vec3(outward.x, 0.0, outward.y);
+        Body {
+            centre: vec3(front.x, 0.0, front.y) + rotation * Self::centre_offset(car),
+            velocity: vec3(heading.x, 0.0, heading.y) * car.speed
+                + outward * excess * THROW
+                + Vec3::unit_y() * HOP,
+            rotation,
+            spin: Vec3::unit_y().cross(outward) * (TIPPING_SPIN + excess * ROLL_RATE),
+        }
+    }
+
+    pub fn matrix(&self, car: &Car) -> Mat4 {
+        let front = self.centre - self.rotation * Self::centre_offset(car);
+        Mat4::from_translation(front) * Mat4::from_quat(self.rotation)
+    }
+
+    pub fn couplers(&self, car: &Car) -> (Vec3, Vec3) {
+        let at = |z: f32| self.matrix(car).transform_point3(vec3(0.0, COUPLER_HEIGHT, z));
+        (at(car.couplers.0), at(car.couplers.1))
+    }
+
+    pub fn velocity_at(&self, at: Vec3) -> Vec3 {
+        self.velocity + self.spin.cross(at - self.centre)
+    }
+
+    pub fn push(&mut self, car: &Car, gauge: f32, at: Vec3, force: Vec3, dt: f32) {
+        self.velocity += force / car.mass * dt;
+        let torque = (at - self.centre).cross(force);
+        let local = self.rotation.conjugate() * torque / Self::inertia(car, gauge);
+        self.spin += self.rotation * local * dt;
+    }
+
+    /// Moves the body along by `dt` seconds, pushing corners that have sunk back up.
+    pub fn step(&mut self, car: &Car, gauge: f32, dt: f32) {
+        self.velocity.y -= GRAVITY * dt;
+
+        let matrix = self.matrix(car);
+        let half_width = gauge * WIDTH / 2.0;
+        // Worked out for every corner before any are pushed on.
+        let mut pushes = vec![];
+        for &x in &[-half_width, half_width] {
+            for &y in &[0.0, car.centre_height * 2.0] {
+                for &z in &[0.0, car.length] {
+                    let corner = matrix.transform_point3(vec3(x, y, z));
+                    if corner.y >= 0.0 {
+                        continue;
+                    }
+
+                    let mass = car.mass / 4.0;
+                    let velocity = self.velocity_at(corner);
+                    let up = (GROUND_STIFFNESS * -corner.y - GROUND_DAMPING * velocity.y) * mass;
+                    let up = up.max(0.0);
+                    let sliding = vec3(velocity.x, 0.0, velocity.z);
+                    let drag = (GROUND_FRICTION * up).min(sliding.length() * GROUND_DAMPING * mass);
+                    let drag = if drag > 0.0 { -sliding.normalize() * drag } else { Vec3::zero() };
+                    pushes.push((corner, Vec3::unit_y() * up + drag));
+                }
+            }
+        }
+        for (corner, force) in pushes {
+            self.push(car, gauge, corner, force, dt);
+        }
+
+        self.centre += self.velocity * dt;
+        let angle = self.spin.length() * dt;
+        if angle > 0.0 {
+            let turn = Quat::from_axis_angle(self.spin.normalize(), angle);
+            self.rotation = (turn * self.rotation).normalize();
+        }
+    }
+}
+
+impl super::super::Stage {
+    /// Derails cars going too fast for their curve, and moves the derailed ones along.
+    pub fn step_derailments(&mut self, dt: f32) {
+        let gauge = self.track_style.gauge;
+        for t in 0..self.trains.len() {
+            for i in 0..self.trains[t].cars.len() {
+                let car = &self.trains[t].cars[i];
+                if car.body.is_some() {
+                    continue;
+                }
+
+                // The middle of the car's thrown outwards by v²·curvature.
+                let middle = car.travelled - car.length / 2.0;
+                let curvature = self.track_curves.curvature(middle);
+                let thrown = car.speed * car.speed * curvature.abs();
+                let limit = car.tipping_point(gauge);
+                if thrown > limit {
+                    let outward = -self.track_heading(middle).perp() * curvature.signum();
+                    self.derail(t, i, outward, thrown - limit);
+                }
+            }
+
+            for i in 0..self.trains[t].cars.len().saturating_sub(1) {
+                let (front, back) = (&self.trains[t].cars[i], &self.trains[t].cars[i + 1]);
+                if front.body.is_none() && back.body.is_none() {
+                    continue;
+                }
+
+                let (a, a_velocity) = self.coupler_face(front, true);
+                let (b, b_velocity) = self.coupler_face(back, false);
+                let apart = b - a;
+                let stretch = apart.length();
+                let toward = if stretch > 0.0 { apart / stretch } else { Vec3::zero() };
+                let tension = front.coupler.tension(stretch, toward.dot(b_velocity - a_velocity));
+                self.tug(t, i, a, toward * tension, dt);
+                self.tug(t, i + 1, b, -toward * tension, dt);
+            }
+
+            for car in &mut self.trains[t].cars {
+                if let Some(mut body) = car.body.take() {
+                    body.step(car, gauge, dt);
+                    car.body = Some(body);
+                }
+            }
+        }
+    }
+
+    /// Where a coupler face of `car` is, and how fast it's moving.
+    fn coupler_face(&self, car: &Car, back: bool) -> (Vec3, Vec3) {
+        match &car.body {
+            Some(body) => {
+                let (front_face, back_face) = body.couplers(car);
+                let at = if back { back_face } else { front_face };
+                (at, body.velocity_at(at))
+            }
+            None => {
+                let dist = car.travelled - if back { car.couplers.1 } else { car.couplers.0 };
+                let Vec2 { x, y } = self.track_point(dist);
+                let Vec2 { x: dx, y: dz } = self.track_heading(dist) * car.speed;
+                (vec3(x, COUPLER_HEIGHT, y), vec3(dx, 0.0, dz))
+            }
+        }
+    }
+
+    /// Pulls on car `i` of train `t`, dragging it off the track if that's too hard sideways.
+    fn tug(&mut self, t: usize, i: usize, at: Vec3, force: Vec3, dt: f32) {
+        let gauge = self.track_style.gauge;
+        let car = &mut self.trains[t].cars[i];
+        if let Some(mut body) = car.body.take() {
+            body.push(car, gauge, at, force, dt);
+            car.body = Some(body);
+            return;
+        }
+
+        let middle = car.travelled - car.length / 2.0;
+        let heading = self.track_heading(middle);
+        let along = force.dot(vec3(heading.x, 0.0, heading.y));
+        let car = &mut self.trains[t].cars[i];
+        car.speed += along / car.mass * dt;
+
+        let sideways = force - vec3(heading.x, 0.0, heading.y) * along;
+        let (thrown, limit) = (sideways.length() / car.mass, car.tipping_point(gauge));
+        if thrown > limit {
+            let outward = Vec2::new(sideways.x, sideways.z).normalize();
+            self.derail(t, i, outward, thrown - limit);
+        }
+    }
+
+    fn derail(&mut self, t: usize, i: usize, outward: Vec2, excess: f32) {
+        let car = &self.trains[t].cars[i];
+        let (front, to_back) = self.car_pose(car, car.travelled);
+        let heading = self.track_heading(car.travelled - car.length / 2.0);
+        let body = Box::new(Body::derail(car, front, to_back, heading, outward, excess));
+
+        let car = &mut self.trains[t].cars[i];
+        car.body = Some(body);
+        car.speed = 0.0;
+        self.events.push(super::Event::Derailed { train: t, car: i });
+    }
+}
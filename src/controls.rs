@@ -0,0 +1,229 @@
+use super::{autopilot::Autopilot, cars::Cars, RenderQueue, Tile};
+use glam::{vec2, vec3, Mat4, Vec2};
+use miniquad::KeyCode;
+
+/// How many notches the throttle has, past shut. The last of them is full power.
+pub const NOTCHES: u8 = 8;
+
+/// How much of the way on, or off, the brake goes every second its key is held down.
+const BRAKE_RATE: f32 = 0.5;
+
+/// How fast the train can still be going, in metres a second, for it to count as stopped.
+const STOPPED: f32 = 0.1;
+
+/// How much the autopilot's cruising speed goes up or down by a notch, in metres a second.
+const CRUISE_STEP: f32 = 2.0;
+
+/// Something the driver can do, with a key bound to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// Opens the throttle up a notch.
+    ThrottleUp,
+    /// Closes the throttle down a notch.
+    ThrottleDown,
+    /// Puts the brake on further for as long as it's held.
+    BrakeOn,
+    /// Takes the brake off for as long as it's held.
+    BrakeOff,
+    /// Shuts the throttle and throws the brake all the way on, in one go. Once the train's
+    /// stopped, pressing it again lets the throttle open back up.
+    Emergency,
+    /// Switches the train between driving forwards and backwards, only while it's stopped.
+    Reverser,
+    /// Hands the train over to the autopilot, to hold the speed it's going at, or takes
+    /// it back. While it's engaged, the throttle sets the speed, and the brakes take over.
+    Autopilot,
+}
+
+impl Action {
+    /// What each action is called in `controls.txt`.
+    const NAMES: [(Action, &'static str); 7] = [
+        (Action::ThrottleUp, "throttle-up"),
+        (Action::ThrottleDown, "throttle-down"),
+        (Action::BrakeOn, "brake-on"),
+        (Action::BrakeOff, "brake-off"),
+        (Action::Emergency, "emergency"),
+        (Action::Reverser, "reverser"),
+        (Action::Autopilot, "autopilot"),
+    ];
+}
+
+/// The keys that can be bound, named in `controls.txt` the way they print with `{:?}`.
+#[rustfmt::skip]
+const KEYS: &[KeyCode] = {
+    use KeyCode::*;
+    &[
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+        Kp0, Kp1, Kp2, Kp3, Kp4, Kp5, Kp6, Kp7, Kp8, Kp9, KpAdd, KpSubtract, KpEnter,
+        Up, Down, Left, Right, PageUp, PageDown, Home, End, Insert, Delete,
+        Space, Enter, Backspace, Escape, Minus, Equal, Comma, Period, Slash, Semicolon,
+        Apostrophe, LeftBracket, RightBracket, Backslash,
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    ]
+};
+
+/// Which keys do what. These are the defaults, and `controls.txt` can change them
+/// with lines like `brake-on E Down`: an action, and every key that should do it.
+pub struct Bindings(Vec<(KeyCode, Action)>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings(vec![
+            (KeyCode::W, Action::ThrottleUp),
+            (KeyCode::S, Action::ThrottleDown),
+            (KeyCode::E, Action::BrakeOn),
+            (KeyCode::Q, Action::BrakeOff),
+            (KeyCode::Space, Action::Emergency),
+            (KeyCode::V, Action::Reverser),
+            (KeyCode::P, Action::Autopilot),
+        ])
+    }
+}
+
+impl Bindings {
+    /// The default bindings, with the actions `text` lists bound to its keys instead.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut bindings = Bindings::default();
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            let mut words = line.split('#').next().unwrap().split_whitespace();
+            let name = match words.next() {
+                Some(name) => name,
+                None => continue,
+            };
+
+            let (action, _) = *Action::NAMES
+                .iter()
+                .find(|(_, n)| *n == name)
+                .ok_or_else(|| error(format!("there's no action called {}", name)))?;
+            bindings.0.retain(|&(_, a)| a != action);
+            for word in words {
+                let key = KEYS
+                    .iter()
+                    .find(|key| format!("{:?}", key).eq_ignore_ascii_case(word))
+                    .ok_or_else(|| error(format!("there's no key called {}", word)))?;
+                bindings.0.push((*key, action));
+            }
+        }
+        Ok(bindings)
+    }
+
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        self.0.iter().find(|&&(k, _)| k == key).map(|&(_, action)| action)
+    }
+}
+
+/// Where the levers in the cab of the train being driven are.
+pub struct Controls {
+    /// How many notches open the throttle is.
+    pub notch: u8,
+    /// How far on the brake is, from 0 to 1.
+    pub brake: f32,
+    pub emergency: bool,
+    pub reversed: bool,
+    /// 1 while the brake's being put on, -1 while it's being taken off, 0 otherwise.
+    brake_moving: f32,
+}
+
+impl Controls {
+    /// Takes over driving `train` with its levers wherever they were left.
+    pub fn of(train: &Cars) -> Self {
+        Controls {
+            notch: (train.throttle * NOTCHES as f32).round() as u8,
+            brake: train.brake,
+            emergency: false,
+            reversed: train.reversed,
+            brake_moving: 0.0,
+        }
+    }
+
+    pub fn press(&mut self, action: Action, train: &mut Cars) {
+        let stopped = train.speed().abs() < STOPPED;
+        if let Some(autopilot) = &mut train.autopilot {
+            match action {
+                Action::ThrottleUp => autopilot.cruise += CRUISE_STEP,
+                Action::ThrottleDown => {
+                    autopilot.cruise = (autopilot.cruise - CRUISE_STEP).max(0.0)
+                }
+                Action::Autopilot | Action::BrakeOn | Action::Emergency => {
+                    // The levers are left where the autopilot had them.
+                    train.autopilot = None;
+                    *self = Controls::of(train);
+                }
+                _ => {}
+            }
+            if action != Action::BrakeOn && action != Action::Emergency {
+                return;
+            }
+        }
+
+        match action {
+            Action::ThrottleUp if !self.emergency => self.notch = (self.notch + 1).min(NOTCHES),
+            Action::ThrottleDown => self.notch = self.notch.saturating_sub(1),
+            Action::BrakeOn => self.brake_moving = 1.0,
+            Action::BrakeOff => self.brake_moving = -1.0,
+            Action::Emergency if !self.emergency => {
+                self.emergency = true;
+                self.notch = 0;
+                self.brake = 1.0;
+            }
+            Action::Emergency if stopped => self.emergency = false,
+            Action::Reverser if stopped && self.notch == 0 => self.reversed = !self.reversed,
+            Action::Autopilot => {
+                let speed = train.speed().abs();
+                train.autopilot = Some(Autopilot::new((speed / CRUISE_STEP).round() * CRUISE_STEP));
+            }
+            _ => {}
+        }
+    }
+
+    pub fn release(&mut self, action: Action) {
+        match action {
+            Action::BrakeOn | Action::BrakeOff => self.brake_moving = 0.0,
+            _ => {}
+        }
+    }
+
+    /// Moves the brake along by `dt` seconds if its key's held, and sets `train` going
+    /// however the levers say to. With the autopilot driving, the levers follow it instead.
+    pub fn step(&mut self, dt: f32, train: &mut Cars) {
+        if train.autopilot.is_some() {
+            self.notch = (train.throttle * NOTCHES as f32).round() as u8;
+            self.brake = train.brake;
+            return;
+        }
+
+        let brake = self.brake + self.brake_moving * BRAKE_RATE * dt;
+        self.brake = if self.emergency { 1.0 } else { brake.clamp(0.0, 1.0) };
+
+        train.throttle = self.notch as f32 / NOTCHES as f32;
+        train.brake = self.brake;
+        train.reversed = self.reversed;
+    }
+
+    /// Shows where the levers are, down in the bottom left of a `screen` that many pixels
+    /// across: the throttle's notches, the brake in tenths, the emergency brake, and which
+    /// way the reverser's set, forwards above backwards, and whether the autopilot's driving.
+    pub fn draw(&self, rq: &mut RenderQueue, screen: Vec2, train: &Cars) {
+        const SIZE: f32 = 12.0;
+        let bottom = screen.y - 2.0 * SIZE;
+        let mut tile = |lit: bool, x: f32, y: f32, size: Vec2| {
+            let tile = if lit { Tile::Lit } else { Tile::Unlit };
+            let at = vec3(SIZE * 2.0 + x * SIZE, bottom - y * SIZE, 0.0);
+            rq.draw_tile(tile, Mat4::from_translation(at) * Mat4::from_scale(size.extend(1.0)));
+        };
+        let square = vec2(SIZE, SIZE) / 2.0 * 0.8;
+
+        for notch in 1..=NOTCHES {
+            tile(notch <= self.notch, 0.0, notch as f32 - 1.0, square);
+        }
+        for tenth in 1..=10 {
+            tile(tenth as f32 <= (self.brake * 10.0).round(), 1.0, tenth as f32 - 1.0, square);
+        }
+        tile(self.emergency, 2.5, 0.5, square * 2.0);
+        tile(!self.reversed, 4.0, 1.0, square);
+        tile(self.reversed, 4.0, 0.0, square);
+        tile(train.autopilot.is_some(), 5.5, 0.5, square * 2.0);
+    }
+}
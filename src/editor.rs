@@ -0,0 +1,474 @@
+use super::{RenderQueue, Stage, Tile};
+use glam::{vec2, vec3, Mat4, Vec2, Vec3};
+use miniquad::{Context, KeyCode, KeyMods};
+use train::art::{
+    ArtData, BezierCurve, BezierPoint, Track, TrackStyle, INDEX_COUNT, LOOP_GAP, VERTEX_COUNT,
+};
+use train::lint::Thresholds;
+
+/// How close to a point or handle the mouse has to be to pick it up.
+const GRAB_RADIUS: f32 = 2.5;
+
+/// The sizes of grid that pressing G steps through.
+const GRID_SIZES: [Option<f32>; 3] = [None, Some(1.0), Some(5.0)];
+
+/// Which of a `BezierPoint`'s positions is being moved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Part {
+    Left,
+    Pos,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Grip {
+    point: usize,
+    part: Part,
+}
+
+impl Grip {
+    fn get(self, points: &[BezierPoint]) -> Vec2 {
+        let point = &points[self.point];
+        match self.part {
+            Part::Left => point.left(),
+            Part::Pos => point.pos(),
+            Part::Right => point.right(),
+        }
+    }
+
+    /// Moves this part of the point to `to`. Moving the point itself
+    /// brings its handles along with it. With `mirror` set, moving one handle
+    /// swings the other one around to face it, keeping the track smooth there.
+    fn set(self, points: &mut [BezierPoint], to: Vec2, mirror: bool) {
+        let point = &mut points[self.point];
+        let mirrored = (point.pos() * 2.0 - to).into();
+        match self.part {
+            Part::Left => {
+                point.left = to.into();
+                if mirror {
+                    point.right = mirrored;
+                }
+            }
+            Part::Right => {
+                point.right = to.into();
+                if mirror {
+                    point.left = mirrored;
+                }
+            }
+            Part::Pos => {
+                let moved = to - point.pos();
+                point.left = (point.left() + moved).into();
+                point.right = (point.right() + moved).into();
+                point.pos = to.into();
+            }
+        }
+    }
+}
+
+/// One change to the points, kept around so that it can be taken back:
+/// the points starting at `at` that were `before` were swapped out for `after`.
+/// Moves, inserts and deletes all boil down to this.
+#[derive(Debug, Clone)]
+struct Edit {
+    at: usize,
+    before: Vec<BezierPoint>,
+    after: Vec<BezierPoint>,
+}
+
+impl Edit {
+    fn apply(&self, points: &mut Vec<BezierPoint>) {
+        points.splice(self.at..self.at + self.before.len(), self.after.iter().copied());
+    }
+
+    fn revert(&self, points: &mut Vec<BezierPoint>) {
+        points.splice(self.at..self.at + self.after.len(), self.before.iter().copied());
+    }
+}
+
+/// Rules the editor holds the points to while they're dragged around.
+#[derive(Debug, Clone, Copy, Default)]
+struct Constraints {
+    /// Keep each point's handles on opposite sides of it, so the track has no kinks.
+    mirror_handles: bool,
+    /// Snap whatever's being dragged to a grid this size.
+    grid: Option<f32>,
+    /// Refuse moves that bend the track tighter than this.
+    min_radius: Option<f32>,
+}
+
+pub struct Editor {
+    pub points: Vec<BezierPoint>,
+    pub selected: Option<usize>,
+    /// What's being dragged, and how the points were before the dragging started.
+    dragging: Option<(Grip, Vec<BezierPoint>)>,
+    constraints: Constraints,
+    done: Vec<Edit>,
+    undone: Vec<Edit>,
+    /// Whether the points are the same as they were when they were last saved.
+    saved: bool,
+    /// Where the camera looks down at, the middle of the track when the editor was opened.
+    /// It stays put after that, so the ground doesn't slide out from under the mouse.
+    center: Vec3,
+}
+
+impl Editor {
+    fn new(track: &Track) -> Self {
+        let mut points = track.points();
+        // The two ends of a loop are one point, with the handles it has going either way.
+        if track.is_loop() {
+            let last = points.len() - 1;
+            points[0].left = points[last].left;
+            points[last].right = points[0].right;
+        }
+        let (min, max) =
+            points.iter().fold((Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)), |(min, max), p| {
+                (min.min(p.pos()), max.max(p.pos()))
+            });
+        let Vec2 { x, y } = (min + max) / 2.0;
+
+        Self {
+            points,
+            selected: None,
+            dragging: None,
+            constraints: Default::default(),
+            done: vec![],
+            undone: vec![],
+            saved: false,
+            center: vec3(x, 0.0, y),
+        }
+    }
+
+    /// Makes `edit` and remembers it, so that it can be undone.
+    fn commit(&mut self, edit: Edit) {
+        if self.twin(0).is_none() {
+            edit.apply(&mut self.points);
+            return self.record(edit);
+        }
+
+        // The ends of a loop are one point, so changing either end's handle changes both.
+        let before = self.points.clone();
+        edit.apply(&mut self.points);
+        let last = self.points.len() - 1;
+        self.points[0].left = self.points[last].left;
+        self.points[last].right = self.points[0].right;
+        self.record(Edit { at: 0, before, after: self.points.clone() });
+    }
+
+    /// Remembers `edit`, which has already been made.
+    fn record(&mut self, edit: Edit) {
+        self.saved = false;
+        self.done.push(edit);
+        self.undone.clear();
+    }
+
+    /// Lets go of whatever's being dragged, remembering where it was
+    /// picked up from so that the whole drag can be undone at once.
+    fn finish_drag(&mut self) {
+        if let Some((_, before)) = self.dragging.take() {
+            // Only the points that moved go in the edit, which is usually just the one,
+            // but both ends of a loop move together.
+            let same = |(a, b): (&BezierPoint, &BezierPoint)| {
+                (a.left, a.pos, a.right) == (b.left, b.pos, b.right)
+            };
+            let pairs = || before.iter().zip(&self.points);
+            if let Some(first) = pairs().position(|pair| !same(pair)) {
+                let last = before.len() - 1 - pairs().rev().position(|pair| !same(pair)).unwrap();
+                self.record(Edit {
+                    at: first,
+                    before: before[first..=last].to_vec(),
+                    after: self.points[first..=last].to_vec(),
+                });
+            }
+        }
+    }
+
+    /// Undoes the last edit for good, so that it can't be redone either.
+    fn take_back(&mut self) {
+        self.undo();
+        self.undone.pop();
+    }
+
+    fn undo(&mut self) {
+        // A drag that's still going is finished first, so it's what gets undone.
+        self.finish_drag();
+        if let Some(edit) = self.done.pop() {
+            self.saved = false;
+            edit.revert(&mut self.points);
+            self.selected = Some(edit.at.min(self.points.len() - 1));
+            self.undone.push(edit);
+        }
+    }
+
+    fn redo(&mut self) {
+        self.finish_drag();
+        if let Some(edit) = self.undone.pop() {
+            self.saved = false;
+            edit.apply(&mut self.points);
+            self.selected = Some(edit.at.min(self.points.len() - 1));
+            self.done.push(edit);
+        }
+    }
+
+    /// Moves `grip` as close to `to` as the constraints allow.
+    fn drag(&mut self, grip: Grip, to: Vec2) {
+        let Constraints { mirror_handles, grid, min_radius } = self.constraints;
+        let to = match grid {
+            Some(size) => (to / size).round() * size,
+            None => to,
+        };
+
+        let was = self.points[grip.point];
+        let twin = self.twin(grip.point);
+        let tightest_before = self.tightest_radius_around(grip.point);
+        grip.set(&mut self.points, to, mirror_handles);
+
+        if let Some(min_radius) = min_radius {
+            let tightest = self.tightest_radius_around(grip.point);
+            if tightest < min_radius && tightest < tightest_before {
+                self.points[grip.point] = was;
+            }
+        }
+        if let Some(twin) = twin {
+            self.points[twin] = self.points[grip.point];
+        }
+    }
+
+    /// The other end of the track, if point `i` is one end of a loop. The two are welded
+    /// together, so that moving one doesn't pull the loop open.
+    fn twin(&self, i: usize) -> Option<usize> {
+        let last = self.points.len() - 1;
+        let (first_pos, last_pos) = (self.points[0].pos(), self.points[last].pos());
+        match i {
+            _ if last < 2 || first_pos.distance(last_pos) >= LOOP_GAP => None,
+            0 => Some(last),
+            i if i == last => Some(0),
+            _ => None,
+        }
+    }
+
+    /// The tightest radius either of the curves running into or out of point `i` bends to.
+    fn tightest_radius_around(&self, i: usize) -> f32 {
+        let curves = i.saturating_sub(1)..(i + 1).min(self.points.len() - 1);
+        curves
+            .map(|c| BezierCurve::new(self.points[c], self.points[c + 1]))
+            .flat_map(|curve| (0..=32).map(move |n| 1.0 / curve.curvature(n as f32 / 32.0).abs()))
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    fn grip_near(&self, p: Vec2) -> Option<Grip> {
+        (0..self.points.len())
+            .flat_map(|point| {
+                // Handles come first so that one sitting on its point can still be grabbed.
+                [Part::Left, Part::Right, Part::Pos].iter().map(move |&part| Grip { point, part })
+            })
+            .map(|grip| (grip, grip.get(&self.points).distance(p)))
+            .filter(|&(_, dist)| dist < GRAB_RADIUS)
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .map(|(grip, _)| grip)
+    }
+
+    /// Splits the curve after the selected point in two, without changing its shape.
+    /// If the last point is selected, the track is carried on straight past it instead.
+    fn insert(&mut self) {
+        self.finish_drag();
+        let i = match self.selected {
+            // The last point of a loop is the first one too, so it carries on from there.
+            Some(i) if i == self.points.len() - 1 && self.twin(i).is_some() => 0,
+            Some(i) => i,
+            None => return,
+        };
+
+        let this = self.points[i];
+        let edit = if let Some(&next) = self.points.get(i + 1) {
+            let (p0, p1, p2, p3) = (this.pos(), this.right(), next.left(), next.pos());
+            let (a, b, c) = (p0.lerp(p1, 0.5), p1.lerp(p2, 0.5), p2.lerp(p3, 0.5));
+            let (d, e) = (a.lerp(b, 0.5), b.lerp(c, 0.5));
+
+            let middle =
+                BezierPoint { left: d.into(), pos: d.lerp(e, 0.5).into(), right: e.into() };
+            Edit {
+                at: i,
+                before: vec![this, next],
+                after: vec![
+                    BezierPoint { right: a.into(), ..this },
+                    middle,
+                    BezierPoint { left: c.into(), ..next },
+                ],
+            }
+        } else {
+            // With both handles sat on the point, it carries on from the point before instead.
+            let heading = match self.points.get(i.wrapping_sub(1)) {
+                _ if this.right() != this.left() => this.right() - this.left(),
+                Some(prev) if prev.pos() != this.pos() => this.pos() - prev.pos(),
+                _ => Vec2::unit_x(),
+            };
+            let ahead = heading.normalize() * 20.0;
+            let pos = this.pos() + ahead;
+            let end = BezierPoint {
+                left: (pos - ahead / 3.0).into(),
+                pos: pos.into(),
+                right: (pos + ahead / 3.0).into(),
+            };
+            Edit { at: i, before: vec![this], after: vec![this, end] }
+        };
+        self.commit(edit);
+        self.selected = Some(i + 1);
+    }
+
+    fn delete(&mut self) {
+        self.finish_drag();
+        let i = match self.selected {
+            Some(i) => i,
+            None => return,
+        };
+        let last = self.points.len() - 1;
+        match self.twin(i) {
+            // Both ends of a loop go, and it's closed up again at the next point along.
+            Some(_) if last > 3 => {
+                let mut after = self.points[1..last].to_vec();
+                after.push(after[0]);
+                self.commit(Edit { at: 0, before: self.points.clone(), after });
+                self.selected = Some(0);
+            }
+            Some(_) => {}
+            None if last > 1 => {
+                self.commit(Edit { at: i, before: vec![self.points[i]], after: vec![] });
+                self.selected = Some(i.saturating_sub(1));
+            }
+            None => {}
+        }
+    }
+
+    /// Shows how the editor's set up, down in the bottom right of a `screen` that many
+    /// pixels across: whether the handles are mirrored, how big a grid's snapped to, with
+    /// the bigger ones lighting more up, whether curves are kept from getting too tight,
+    /// and whether the track's been saved since it was last changed.
+    pub fn draw(&self, rq: &mut RenderQueue, screen: Vec2) {
+        const SIZE: f32 = 12.0;
+        let Constraints { mirror_handles, grid, min_radius } = self.constraints;
+        let grid = GRID_SIZES.iter().position(|&size| size == grid).unwrap_or(0);
+        let shown = [mirror_handles, grid >= 1, grid >= 2, min_radius.is_some(), self.saved];
+        let square = Vec2::splat(SIZE / 2.0 * 0.8).extend(1.0);
+        for (n, &on) in shown.iter().rev().enumerate() {
+            let tile = if on { Tile::Lit } else { Tile::Unlit };
+            let at = vec3(screen.x - SIZE * (2.0 + n as f32 * 1.5), screen.y - SIZE * 2.0, 0.0);
+            rq.draw_tile(tile, Mat4::from_translation(at) * Mat4::from_scale(square));
+        }
+    }
+
+    /// Writes the points out the same way `track_script.py` does, in place of the first
+    /// track in `track.json`. Any other tracks in there are kept.
+    fn save(&self) -> std::io::Result<()> {
+        use std::io::{Error, ErrorKind};
+        let json = match std::fs::read_to_string("track.json") {
+            Ok(old) => BezierPoint::replace_first_json(&old, &self.points).ok_or_else(|| {
+                Error::new(ErrorKind::InvalidData, "track.json isn't a list of tracks")
+            })?,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                BezierPoint::to_json(std::slice::from_ref(&self.points))
+            }
+            Err(e) => return Err(e),
+        };
+        std::fs::write("track.json", json)
+    }
+}
+
+/// Whether the track through `points`, and the markers on them, fit in the art
+/// after the `art_len` vertices and indices that are kept when it's rebuilt.
+fn fits(points: &[BezierPoint], art_len: (u32, u32), style: &TrackStyle) -> bool {
+    let track = ArtData::track_size(&Track::from_points(points), style);
+    let gizmos = ArtData::gizmos_size(points);
+    (art_len.0 + track.0 + gizmos.0) as usize <= VERTEX_COUNT
+        && (art_len.1 + track.1 + gizmos.1) as usize <= INDEX_COUNT
+}
+
+impl Stage {
+    pub fn editor_key_down(&mut self, ctx: &mut Context, keycode: KeyCode, keymods: KeyMods) {
+        if keycode == KeyCode::Tab {
+            self.editor = match self.editor {
+                Some(_) => None,
+                None => Some(Editor::new(&self.track_curves)),
+            };
+            self.rebuild_track(ctx);
+            return;
+        }
+
+        let editor = match &mut self.editor {
+            Some(editor) => editor,
+            None => return,
+        };
+        let constraints = &mut editor.constraints;
+        match keycode {
+            KeyCode::Insert | KeyCode::I => {
+                editor.insert();
+                if !fits(&editor.points, self.art_len, &self.track_style) {
+                    editor.take_back();
+                }
+            }
+            KeyCode::Delete | KeyCode::X => editor.delete(),
+            KeyCode::Z if keymods.ctrl && keymods.shift => editor.redo(),
+            KeyCode::Z if keymods.ctrl => editor.undo(),
+            KeyCode::Y if keymods.ctrl => editor.redo(),
+            KeyCode::M => constraints.mirror_handles = !constraints.mirror_handles,
+            KeyCode::G => {
+                let now = GRID_SIZES.iter().position(|&size| size == constraints.grid);
+                constraints.grid = GRID_SIZES[now.map_or(0, |i| (i + 1) % GRID_SIZES.len())];
+            }
+            KeyCode::R => {
+                constraints.min_radius = match constraints.min_radius {
+                    Some(_) => None,
+                    None => Some(Thresholds::default().min_radius),
+                };
+            }
+            KeyCode::S => match editor.save() {
+                Ok(()) => editor.saved = true,
+                Err(e) => eprintln!("couldn't save the track: {}", e),
+            },
+            _ => return,
+        }
+        self.track_curves = Track::from_points(&editor.points);
+        self.rebuild_track(ctx);
+    }
+
+    pub fn editor_grab(&mut self, ctx: &mut Context) {
+        let mouse = vec2(self.mouse_on_ground.x, self.mouse_on_ground.z);
+        if let Some(editor) = &mut self.editor {
+            let grip = editor.grip_near(mouse);
+            editor.dragging = grip.map(|grip| (grip, editor.points.clone()));
+            editor.selected = grip.map(|grip| grip.point);
+            self.rebuild_track(ctx);
+        }
+    }
+
+    /// Lets go of whatever's being dragged.
+    pub fn editor_release(&mut self) {
+        if let Some(editor) = &mut self.editor {
+            editor.finish_drag();
+        }
+    }
+
+    /// Looks down on the track from high above it, and keeps whatever's
+    /// being dragged under the mouse.
+    pub fn update_editor(&mut self, ctx: &mut Context) {
+        let editor = match &mut self.editor {
+            Some(editor) => editor,
+            None => return,
+        };
+
+        self.cam_origin = editor.center;
+        self.cam_offset = vec3(0.0, 240.0, 60.0);
+
+        let mouse = vec2(self.mouse_on_ground.x, self.mouse_on_ground.z);
+        if let Some(grip) = editor.dragging.as_ref().map(|&(grip, _)| grip) {
+            if grip.get(&editor.points).distance(mouse) > f32::EPSILON {
+                let was = editor.points.clone();
+                editor.drag(grip, mouse);
+                if !fits(&editor.points, self.art_len, &self.track_style) {
+                    editor.points = was;
+                }
+                self.track_curves = Track::from_points(&editor.points);
+                self.rebuild_track(ctx);
+            }
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! Writes tracks out for other programs to show: as an SVG plan to look over,
+//! and as a glTF model of the geometry `ArtData::make_track` builds.
+use crate::art::{BezierCurve, Track, TrackStyle, Vertex};
+use glam::Vec2;
+use std::fmt::Write;
+
+/// Pixels in the SVG for every metre of track.
+const SVG_SCALE: f32 = 4.0;
+
+/// A plan of `tracks` seen from above, in metres: the centreline of each track, the two
+/// rails `style.gauge` apart, a mark across the track wherever one curve hands over to
+/// the next, and how long each curve is written beside it.
+pub fn svg(tracks: &[Track], style: &TrackStyle) -> String {
+    let margin = style.ballast_bottom_width * 2.0;
+    let (mut min, mut max) = (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN));
+    for point in tracks.iter().flat_map(|track| track.tessellate(0.1)) {
+        min = min.min(point);
+        max = max.max(point);
+    }
+    let (min, size) = (min - Vec2::splat(margin), max - min + Vec2::splat(margin * 2.0));
+
+    let mut svg = String::new();
+    write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+        size.x * SVG_SCALE,
+        size.y * SVG_SCALE,
+        min.x,
+        min.y,
+        size.x,
+        size.y
+    )
+    .unwrap();
+    svg.push_str(
+        r#"
+<style>
+  .rail { fill: none; stroke: #555; stroke-width: 0.16; }
+  .centreline { fill: none; stroke: #c33; stroke-width: 0.1; stroke-dasharray: 1 0.5; }
+  .boundary { stroke: #36c; stroke-width: 0.2; }
+  text { font: 2.5px sans-serif; fill: #36c; text-anchor: middle; }
+</style>
+"#,
+    );
+
+    let total: f32 = tracks.iter().map(Track::len).sum();
+    writeln!(
+        svg,
+        "<text x=\"{}\" y=\"{}\" style=\"text-anchor: start\">{} track{}, {:.1} m</text>",
+        min.x + 1.0,
+        min.y + 3.0,
+        tracks.len(),
+        if tracks.len() == 1 { "" } else { "s" },
+        total
+    )
+    .unwrap();
+
+    for (i, track) in tracks.iter().enumerate() {
+        writeln!(svg, "<g id=\"track-{}\">", i).unwrap();
+
+        let path = track.path(0.0..track.len(), style.tolerance);
+        for &side in &[-0.5, 0.5] {
+            let mut points = String::new();
+            for p in &path {
+                let Vec2 { x, y } = p.pos + p.forward.perp() * style.gauge * side;
+                write!(points, "{:.3},{:.3} ", x, y).unwrap();
+            }
+            writeln!(svg, "<polyline class=\"rail\" points=\"{}\"/>", points.trim_end()).unwrap();
+        }
+
+        let mut d = String::new();
+        for (n, curve) in track.curves().iter().enumerate() {
+            let BezierCurve { start, left, right, end } = *curve;
+            if n == 0 {
+                write!(d, "M {:.3},{:.3} ", start.x, start.y).unwrap();
+            }
+            write!(
+                d,
+                "C {:.3},{:.3} {:.3},{:.3} {:.3},{:.3} ",
+                left.x, left.y, right.x, right.y, end.x, end.y
+            )
+            .unwrap();
+        }
+        writeln!(svg, "<path class=\"centreline\" d=\"{}\"/>", d.trim_end()).unwrap();
+
+        let curves = track.curves();
+        let boundaries = curves.iter().map(|c| (c.start, c.derivative(0.0)));
+        let end = curves.last().filter(|_| !track.is_loop()).map(|c| (c.end, c.derivative(1.0)));
+        for (at, heading) in boundaries.chain(end) {
+            let across = heading.normalize().perp() * style.ballast_bottom_width / 2.0;
+            let (a, b) = (at - across, at + across);
+            writeln!(
+                svg,
+                "<line class=\"boundary\" x1=\"{:.3}\" y1=\"{:.3}\" x2=\"{:.3}\" y2=\"{:.3}\"/>",
+                a.x, a.y, b.x, b.y
+            )
+            .unwrap();
+        }
+
+        for curve in curves {
+            let beside = curve.derivative(0.5).normalize().perp() * style.ballast_bottom_width;
+            let Vec2 { x, y } = curve.point(0.5) + beside;
+            writeln!(svg, "<text x=\"{:.3}\" y=\"{:.3}\">{:.1} m</text>", x, y, curve.len())
+                .unwrap();
+        }
+
+        svg.push_str("</g>\n");
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// A glTF model of the triangles `indices` picks out of `vertices`, textured with `image`,
+/// a 16 by 16 RGBA picture like `ArtData::image`. Returns the `.gltf` file, and the binary
+/// buffer it expects to find beside it as `bin_name`.
+pub fn gltf(
+    vertices: &[Vertex],
+    indices: &[i16],
+    image: &[u8],
+    bin_name: &str,
+) -> (String, Vec<u8>) {
+    // Only the vertices that are used go in, numbered from zero.
+    let first = indices.iter().map(|&i| i as usize).min().unwrap_or(0);
+    let last = indices.iter().map(|&i| i as usize + 1).max().unwrap_or(0);
+    let vertices = &vertices[first..last];
+
+    let mut bin = vec![];
+    let mut views = vec![];
+    let mut view = |bin: &mut Vec<u8>, bytes: Vec<u8>, target: Option<u32>| {
+        views.push((bin.len(), bytes.len(), target));
+        bin.extend(bytes);
+        // Every view starts on a multiple of four bytes.
+        bin.resize(bin.len() + (4 - bin.len() % 4) % 4, 0);
+    };
+    let floats = |f: &dyn Fn(&Vertex) -> Vec<f32>| -> Vec<u8> {
+        vertices.iter().flat_map(f).flat_map(|x| x.to_le_bytes().to_vec()).collect()
+    };
+
+    const ARRAY_BUFFER: u32 = 34962;
+    const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+    view(&mut bin, floats(&|v| vec![v.pos.x, v.pos.y, v.pos.z]), Some(ARRAY_BUFFER));
+    view(&mut bin, floats(&|v| vec![v.norm.x, v.norm.y, v.norm.z]), Some(ARRAY_BUFFER));
+    view(&mut bin, floats(&|v| vec![v.uv.x, v.uv.y]), Some(ARRAY_BUFFER));
+    let rebased = indices.iter().flat_map(|&i| (i as u16 - first as u16).to_le_bytes().to_vec());
+    view(&mut bin, rebased.collect(), Some(ELEMENT_ARRAY_BUFFER));
+    view(&mut bin, png(16, 16, image), None);
+
+    let (min, max) =
+        vertices.iter().fold(([f32::MAX; 3], [f32::MIN; 3]), |(min, max), Vertex { pos, .. }| {
+            let pos = [pos.x, pos.y, pos.z];
+            ([0, 1, 2].map(|i| min[i].min(pos[i])), [0, 1, 2].map(|i| max[i].max(pos[i])))
+        });
+
+    let views: Vec<String> = views
+        .iter()
+        .map(|&(offset, len, target)| {
+            let target = target.map_or(String::new(), |t| format!(r#", "target": {}"#, t));
+            format!(r#"{{"buffer": 0, "byteOffset": {}, "byteLength": {}{}}}"#, offset, len, target)
+        })
+        .collect();
+    let accessor = |view: usize, component: u32, count: usize, kind: &str| {
+        format!(
+            r#"{{"bufferView": {}, "componentType": {}, "count": {}, "type": "{}""#,
+            view, component, count, kind
+        )
+    };
+    const FLOAT: u32 = 5126;
+    const UNSIGNED_SHORT: u32 = 5123;
+    const NEAREST: u32 = 9728;
+    let accessors = [
+        format!(
+            r#"{}, "min": [{}, {}, {}], "max": [{}, {}, {}]}}"#,
+            accessor(0, FLOAT, vertices.len(), "VEC3"),
+            min[0],
+            min[1],
+            min[2],
+            max[0],
+            max[1],
+            max[2]
+        ),
+        accessor(1, FLOAT, vertices.len(), "VEC3") + "}",
+        accessor(2, FLOAT, vertices.len(), "VEC2") + "}",
+        accessor(3, UNSIGNED_SHORT, indices.len(), "SCALAR") + "}",
+    ];
+
+    let json = format!(
+        r#"{{
+  "asset": {{"version": "2.0", "generator": "train export-track"}},
+  "scene": 0,
+  "scenes": [{{"nodes": [0]}}],
+  "nodes": [{{"name": "track", "mesh": 0}}],
+  "meshes": [{{"name": "track", "primitives": [{{
+    "attributes": {{"POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2}},
+    "indices": 3,
+    "material": 0
+  }}]}}],
+  "materials": [{{
+    "name": "track",
+    "pbrMetallicRoughness": {{"baseColorTexture": {{"index": 0}}, "metallicFactor": 0}}
+  }}],
+  "textures": [{{"source": 0, "sampler": 0}}],
+  "samplers": [{{"magFilter": {nearest}, "minFilter": {nearest}}}],
+  "images": [{{"bufferView": 4, "mimeType": "image/png"}}],
+  "accessors": [
+    {accessors}
+  ],
+  "bufferViews": [
+    {views}
+  ],
+  "buffers": [{{"uri": "{bin_name}", "byteLength": {len}}}]
+}}
+"#,
+        nearest = NEAREST,
+        accessors = accessors.join(",\n    "),
+        views = views.join(",\n    "),
+        bin_name = bin_name,
+        len = bin.len(),
+    );
+    (json, bin)
+}
+
+/// Packs RGBA pixels into a PNG, uncompressed, which is plenty for a picture this small.
+fn png(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    fn chunk(png: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+        png.extend(&(data.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend(kind);
+        png.extend(data);
+        let crc = crc32(&png[start..]);
+        png.extend(&crc.to_be_bytes());
+    }
+
+    // Every row starts with a byte saying it isn't filtered.
+    let row = width as usize * 4;
+    let raw: Vec<u8> =
+        rgba.chunks(row).flat_map(|r| std::iter::once(0).chain(r.iter().copied())).collect();
+
+    // A zlib stream of "stored" deflate blocks, each holding up to 65535 bytes as they are.
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = raw.chunks(0xffff).collect::<Vec<_>>();
+    for (i, block) in blocks.iter().enumerate() {
+        zlib.push((i == blocks.len() - 1) as u8);
+        let len = block.len() as u16;
+        zlib.extend(&len.to_le_bytes());
+        zlib.extend(&(!len).to_le_bytes());
+        zlib.extend(*block);
+    }
+    let (a, b) = raw.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    zlib.extend(&(b << 16 | a).to_be_bytes());
+
+    let mut header = vec![];
+    header.extend(&width.to_be_bytes());
+    header.extend(&height.to_be_bytes());
+    // 8 bits a channel, RGBA, and no interlacing.
+    header.extend(&[8, 6, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"IDAT", &zlib);
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
@@ -0,0 +1,63 @@
+//! Writes the tracks in a `track.json` out as an SVG plan and as a glTF model,
+//! so a layout can be looked over without starting the game up.
+//! The model's texture comes out of `train.cedset`, so that needs to be built first.
+use std::io::Read;
+
+use train::art::{ArtData, BezierPoint, Track, TrackStyle, IMAGE_SIZE};
+use train::export;
+
+fn main() {
+    let mut input = "track.json".to_string();
+    let mut svg = "track.svg".to_string();
+    let mut gltf = "track.gltf".to_string();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| panic!("{} needs a file name", arg));
+        match arg.as_str() {
+            "--svg" => svg = value(),
+            "--gltf" => gltf = value(),
+            _ if !arg.starts_with("--") => input = arg,
+            other => panic!(
+                "unknown argument {}, usage: export-track [track.json] [--svg <plan.svg>] \
+                 [--gltf <track.gltf>]",
+                other
+            ),
+        }
+    }
+
+    let paths: Vec<Vec<BezierPoint>> =
+        serde_json::from_str(&std::fs::read_to_string(&input).unwrap()).unwrap();
+    let tracks: Vec<Track> = paths.iter().map(|points| Track::from_points(points)).collect();
+    let style = TrackStyle::default();
+
+    std::fs::write(&svg, export::svg(&tracks, &style)).unwrap();
+    println!("wrote {}", svg);
+
+    let mut data = Box::new(ArtData::default());
+    let mut indices = 0..0;
+    for (i, track) in tracks.iter().enumerate() {
+        if let Err(e) = data.make_track(track, &style) {
+            panic!(
+                "track {} of {} doesn't fit in the model with the ones before it: {}",
+                i, input, e
+            );
+        }
+        let (start, count) = data.track_indices;
+        if indices.is_empty() {
+            indices.start = start as usize;
+        }
+        indices.end = (start + count) as usize;
+    }
+
+    // The image comes first in a cedset.
+    let mut image = [0; IMAGE_SIZE];
+    std::fs::File::open("train.cedset").unwrap().read_exact(&mut image).unwrap();
+
+    let bin = std::path::Path::new(&gltf).with_extension("bin");
+    let bin_name = bin.file_name().unwrap().to_str().unwrap();
+    let (json, bytes) = export::gltf(&data.vertices, &data.indices[indices], &image, bin_name);
+    std::fs::write(&gltf, json).unwrap();
+    std::fs::write(&bin, bytes).unwrap();
+    println!("wrote {} and {}", gltf, bin.display());
+}
@@ -0,0 +1,181 @@
+//! Makes up closed tracks from a seed.
+//!
+//! The corners of a polygon are scattered around the middle of the bounds, sorted by their
+//! angle so the polygon can't cross itself, and each corner is rounded off with an arc no
+//! tighter than the minimum radius. Anything that still doesn't pass `lint` is thrown out
+//! and another polygon is tried.
+use crate::art::{BezierPoint, Track};
+use crate::lint::{lint, Thresholds};
+use glam::{vec2, Vec2};
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    pub seed: u64,
+    /// About how long the track should come out. It comes out shorter
+    /// if a track that long won't fit in the bounds.
+    pub length: f32,
+    /// The tightest any curve is allowed to bend.
+    pub min_radius: f32,
+    /// How far from the origin the track may reach along each axis.
+    pub bounds: Vec2,
+    /// From 0 to 1, how much of the track should be curves rather than straights.
+    pub curviness: f32,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            length: 600.0,
+            min_radius: Thresholds::default().min_radius,
+            bounds: vec2(150.0, 150.0),
+            curviness: 0.5,
+        }
+    }
+}
+
+/// Returns the points of a closed track, the last one sitting on top of the first.
+pub fn generate(layout: &Layout) -> Vec<BezierPoint> {
+    let mut rng = Rng(layout.seed ^ 0x9e37_79b9_7f4a_7c15);
+    let thresholds = Thresholds { min_radius: layout.min_radius, ..Default::default() };
+
+    for _ in 0..1000 {
+        if let Some(points) = attempt(layout, &mut rng) {
+            if lint(&Track::from_points(&points), &thresholds).lints.is_empty() {
+                return points;
+            }
+        }
+    }
+
+    circle(layout.min_radius.max(layout.bounds.x.min(layout.bounds.y) * 0.8))
+}
+
+fn attempt(layout: &Layout, rng: &mut Rng) -> Option<Vec<BezierPoint>> {
+    let corner_count = 4 + (rng.next() * 7.0) as usize;
+    let mut angles: Vec<f32> = (0..corner_count).map(|_| rng.next() * TAU).collect();
+    angles.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let corners: Vec<Vec2> = angles
+        .iter()
+        .map(|&angle| {
+            let (y, x) = angle.sin_cos();
+            vec2(x, y) * layout.bounds * (0.4 + rng.next() * 0.6)
+        })
+        .collect();
+
+    let perimeter: f32 =
+        (0..corner_count).map(|i| corners[i].distance(corners[(i + 1) % corner_count])).sum();
+    let scale = (layout.length / perimeter).min(1.0);
+    let corners: Vec<Vec2> = corners.iter().map(|&c| c * scale).collect();
+
+    let mut arcs = Vec::with_capacity(corner_count);
+    for i in 0..corner_count {
+        let prev = corners[(i + corner_count - 1) % corner_count];
+        let (corner, next) = (corners[i], corners[(i + 1) % corner_count]);
+        let (into, out) = ((corner - prev).normalize(), (next - corner).normalize());
+        let turn = into.angle_between(out);
+        if !(0.01..=PI - 0.2).contains(&turn) {
+            return None;
+        }
+
+        // Arcs leave at least a tenth of each edge straight, so no curve comes out degenerate.
+        let reach = 0.45 * corner.distance(prev).min(corner.distance(next));
+        let max_radius = reach / (turn / 2.0).tan();
+        let min_radius = layout.min_radius * 1.05;
+        if max_radius < min_radius {
+            return None;
+        }
+
+        let radius = min_radius + (max_radius - min_radius) * layout.curviness * rng.next();
+        let tangent = radius * (turn / 2.0).tan();
+        arcs.push(Arc {
+            start: corner - into * tangent,
+            into,
+            radius,
+            turn: turn * into.perp_dot(out).signum(),
+        });
+    }
+
+    let mut points = vec![];
+    for (i, arc) in arcs.iter().enumerate() {
+        let next_start = arcs[(i + 1) % arcs.len()].start;
+        let prev = &arcs[(i + arcs.len() - 1) % arcs.len()];
+        let straight_in = prev.end().distance(arc.start) / 3.0;
+        let straight_out = arc.end().distance(next_start) / 3.0;
+        points.extend(arc.points(straight_in, straight_out));
+    }
+    points.push(points[0]);
+
+    Some(points)
+}
+
+/// A circular corner starting at `start`, heading along `into`
+/// and turning by `turn` radians, counterclockwise if positive.
+struct Arc {
+    start: Vec2,
+    into: Vec2,
+    radius: f32,
+    turn: f32,
+}
+
+impl Arc {
+    fn center(&self) -> Vec2 {
+        self.start + self.into.perp() * self.radius * self.turn.signum()
+    }
+
+    fn heading(&self, t: f32) -> Vec2 {
+        let (y, x) = (self.turn * t).sin_cos();
+        vec2(self.into.x * x - self.into.y * y, self.into.x * y + self.into.y * x)
+    }
+
+    fn point(&self, t: f32) -> Vec2 {
+        self.center() - self.heading(t).perp() * self.radius * self.turn.signum()
+    }
+
+    fn end(&self) -> Vec2 {
+        self.point(1.0)
+    }
+
+    /// The arc as Bezier points, one more than it takes curves to follow it well. The handles
+    /// at either end reach `straight_in` and `straight_out` along the straights beside it.
+    fn points(&self, straight_in: f32, straight_out: f32) -> Vec<BezierPoint> {
+        let pieces = (self.turn.abs() / FRAC_PI_2).ceil().max(1.0) as usize;
+        let piece_turn = self.turn.abs() / pieces as f32;
+        let handle = 4.0 / 3.0 * (piece_turn / 4.0).tan() * self.radius;
+
+        (0..=pieces)
+            .map(|n| {
+                let t = n as f32 / pieces as f32;
+                let (pos, heading) = (self.point(t), self.heading(t));
+                let left = if n == 0 { straight_in } else { handle };
+                let right = if n == pieces { straight_out } else { handle };
+                BezierPoint {
+                    left: (pos - heading * left).into(),
+                    pos: pos.into(),
+                    right: (pos + heading * right).into(),
+                }
+            })
+            .collect()
+    }
+}
+
+fn circle(radius: f32) -> Vec<BezierPoint> {
+    let arc = Arc { start: vec2(0.0, -radius), into: vec2(1.0, 0.0), radius, turn: TAU };
+    let handle = 4.0 / 3.0 * (FRAC_PI_2 / 4.0).tan() * radius;
+    arc.points(handle, handle)
+}
+
+/// A small, fast random number generator, xorshift64*. Plenty for laying out tracks,
+/// and the same seed always gives the same track on every machine.
+struct Rng(u64);
+
+impl Rng {
+    /// A number from 0 up to, but not including, 1.
+    fn next(&mut self) -> f32 {
+        let Rng(x) = self;
+        *x ^= *x >> 12;
+        *x ^= *x << 25;
+        *x ^= *x >> 27;
+        (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
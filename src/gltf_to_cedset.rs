@@ -1,14 +1,59 @@
 #![feature(array_map)]
 use std::convert::TryInto;
 
-use train::art::{ArtData, ArtIndicesBuilder, Track, Vertex, IMAGE_SIZE};
+use train::art::{
+    ArtData, ArtIndicesBuilder, Track, TrackCurves, TrackStyle, ValveGear, Vertex, IMAGE_SIZE,
+};
+use train::lint::{lint, Thresholds};
 
 // Shamelessly lifted from `https://stackoverflow.com/a/42186553`.
 unsafe fn as_u8_slice<T: Sized>(p: &T) -> &[u8] {
     std::slice::from_raw_parts((p as *const T) as *const u8, std::mem::size_of::<T>())
 }
 
+/// Reads the lint thresholds from the command line, along with
+/// whether any lint at all should stop the cedset from being written.
+fn args() -> (Thresholds, bool) {
+    let mut thresholds = Thresholds::default();
+    let mut deny = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut number = || -> f32 {
+            let value = args.next().unwrap_or_else(|| panic!("{} needs a value", arg));
+            value.parse().unwrap_or_else(|_| panic!("{} isn't a number: {}", arg, value))
+        };
+        match arg.as_str() {
+            "--min-radius" => thresholds.min_radius = number(),
+            "--max-kink" => thresholds.max_kink = number(),
+            "--min-length" => thresholds.min_length = number(),
+            "--deny-lints" => deny = true,
+            other => panic!(
+                "unknown argument {}, try --min-radius, --max-kink, --min-length or --deny-lints",
+                other
+            ),
+        }
+    }
+
+    (thresholds, deny)
+}
+
+/// Where the empties parented to the `train` mesh say its rods go, if they're all there.
+fn valve_gear(doc: &gltf::Document) -> Option<ValveGear> {
+    let train = doc.nodes().find(|node| node.name() == Some("train"))?;
+    let pivot = |name: &str| {
+        let node = train.children().find(|node| node.name() == Some(name))?;
+        Some(node.transform().decomposed().0.into())
+    };
+    Some(ValveGear {
+        crank_pin: pivot("crank_pin")?,
+        crosshead: pivot("crosshead")?,
+        cylinder: pivot("cylinder")?,
+    })
+}
+
 fn main() {
+    let (thresholds, deny_lints) = args();
     let (doc, datas, images) = gltf::import("train.glb").unwrap();
     let mut data = Box::new(ArtData::default());
     let mut art_indices_builder = ArtIndicesBuilder::default();
@@ -66,14 +111,32 @@ fn main() {
     data.image = [(); IMAGE_SIZE].map(|_| pixels.next().unwrap());
 
     data.art_indices = art_indices_builder.unwrap();
+    data.valve_gear = valve_gear(&doc);
+    if data.valve_gear.is_none() {
+        println!("no crank_pin, crosshead and cylinder under train, so it won't have any rods");
+    }
 
     let track: Vec<Vec<_>> =
         serde_json::from_str(&std::fs::read_to_string("track.json").unwrap()).unwrap();
-    data.track = Track::from_points(track.iter().next().unwrap());
+    let mut linted = false;
+    for (i, points) in track.iter().enumerate() {
+        let report = lint(&Track::from_points(points), &thresholds);
+        println!("track {}:\n{}", i, report);
+        linted |= !report.lints.is_empty();
+    }
+    if deny_lints && linted {
+        panic!("the track has lints, and --deny-lints was passed");
+    }
 
     data.last_occupied_vert = current_vert.try_into().unwrap();
     data.last_occupied_index = current_index.try_into().unwrap();
 
+    let first = &track[0];
+    if !data.fits_track(&Track::from_points(first), &TrackStyle::default()) {
+        panic!("the first track in track.json is too big to build along with the art");
+    }
+    data.track = TrackCurves::from_points(first);
+
     unsafe {
         std::fs::write("train.cedset", as_u8_slice::<ArtData>(&data)).unwrap();
     }
@@ -1,4 +1,6 @@
 #![feature(array_map)]
-#![feature(min_const_generics)]
 
 pub mod art;
+pub mod export;
+pub mod generate;
+pub mod lint;
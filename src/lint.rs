@@ -0,0 +1,187 @@
+//! Checks a `Track` for the things that commonly go wrong when drawing one in Blender:
+//! curves too tight for the cars, kinks where two curves meet, curves that cross
+//! over each other, and curves so short they might as well not be there.
+use crate::art::{BezierCurve, Track};
+use glam::Vec2;
+use std::fmt;
+
+/// Past these, `lint` starts complaining.
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+    /// The tightest radius the cars can still get around.
+    pub min_radius: f32,
+    /// The most the track may turn at a join between two curves, in degrees.
+    pub max_kink: f32,
+    /// The shortest a curve may be.
+    pub min_length: f32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self { min_radius: 12.0, max_kink: 2.0, min_length: 0.5 }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Lint {
+    TightCurve {
+        curve: usize,
+        radius: f32,
+    },
+    /// The track turns by `angle` degrees where `curve` meets the curve after it.
+    Kink {
+        curve: usize,
+        angle: f32,
+    },
+    Degenerate {
+        curve: usize,
+        length: f32,
+    },
+    Crossing {
+        curves: (usize, usize),
+        at: Vec2,
+    },
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Lint::TightCurve { curve, radius } => {
+                write!(f, "curve {} bends to a radius of {:.2}", curve, radius)
+            }
+            Lint::Kink { curve, angle } => {
+                write!(f, "curve {} meets the next one at a {:.1} degree kink", curve, angle)
+            }
+            Lint::Degenerate { curve, length } => {
+                write!(f, "curve {} is only {:.3} long", curve, length)
+            }
+            Lint::Crossing { curves: (a, b), at } => {
+                write!(f, "curves {} and {} cross at ({:.2}, {:.2})", a, b, at.x, at.y)
+            }
+        }
+    }
+}
+
+pub struct Report {
+    /// The tightest radius each curve bends to.
+    pub min_radii: Vec<f32>,
+    pub lints: Vec<Lint>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (curve, radius) in self.min_radii.iter().enumerate() {
+            writeln!(f, "  curve {}: tightest radius {:.2}", curve, radius)?;
+        }
+        for lint in &self.lints {
+            writeln!(f, "  warning: {}", lint)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn lint(track: &Track, thresholds: &Thresholds) -> Report {
+    let curves = track.curves();
+    let mut lints = vec![];
+
+    let min_radii: Vec<f32> = curves
+        .iter()
+        .map(|curve| {
+            (0..=64)
+                .map(|n| 1.0 / curve.curvature(n as f32 / 64.0).abs())
+                .fold(f32::INFINITY, f32::min)
+        })
+        .collect();
+
+    for (i, (curve, &radius)) in curves.iter().zip(&min_radii).enumerate() {
+        let length = curve.len();
+        if length < thresholds.min_length {
+            lints.push(Lint::Degenerate { curve: i, length });
+        } else if radius < thresholds.min_radius {
+            lints.push(Lint::TightCurve { curve: i, radius });
+        }
+    }
+
+    let joins = if track.is_loop() { curves.len() } else { curves.len().saturating_sub(1) };
+    for i in 0..joins {
+        let (a, b) = (&curves[i], &curves[(i + 1) % curves.len()]);
+        let angle = heading(a, 1.0).angle_between(heading(b, 0.0)).to_degrees();
+        if angle > thresholds.max_kink {
+            lints.push(Lint::Kink { curve: i, angle });
+        }
+    }
+
+    lints.extend(crossings(track));
+
+    Report { min_radii, lints }
+}
+
+/// Which way the curve heads at `t`, even where a handle sits right on top of its point.
+fn heading(curve: &BezierCurve, t: f32) -> Vec2 {
+    let nudged = if t < 0.5 { t + 0.001 } else { t - 0.001 };
+    let d = curve.derivative(t);
+    if d.length_squared() > f32::EPSILON {
+        d
+    } else {
+        curve.derivative(nudged)
+    }
+}
+
+/// Finds every place where two pieces of the track that aren't right next to each other cross.
+fn crossings(track: &Track) -> Vec<Lint> {
+    let pieces: Vec<Vec<(Vec2, Vec2)>> = track
+        .curves()
+        .iter()
+        .map(|curve| {
+            let mut from = curve.start;
+            curve
+                .tessellate(0.05)
+                .into_iter()
+                .map(|to| (std::mem::replace(&mut from, to), to))
+                .collect()
+        })
+        .collect();
+    // Where each curve's pieces come in the whole track's, so that neighbours can be told.
+    let mut firsts = vec![0];
+    for curve in &pieces {
+        firsts.push(firsts.last().unwrap() + curve.len());
+    }
+    let (looped, total) = (track.is_loop(), *firsts.last().unwrap());
+    let neighbours = |i: usize, j: usize| j < i + 2 || (looped && i == 0 && j == total - 1);
+
+    let mut found: Vec<Lint> = vec![];
+    for curve_a in 0..pieces.len() {
+        // Curves far enough apart to be in different squares of the track's grid can't cross.
+        for curve_b in track.curves_near(curve_a).into_iter().filter(|&b| b >= curve_a) {
+            let at = pieces[curve_a].iter().enumerate().find_map(|(i, &(a0, a1))| {
+                pieces[curve_b].iter().enumerate().find_map(|(j, &(b0, b1))| {
+                    let (i, j) = (firsts[curve_a] + i, firsts[curve_b] + j);
+                    if neighbours(i, j) {
+                        return None;
+                    }
+                    intersect(a0, a1, b0, b1)
+                })
+            });
+            if let Some(at) = at {
+                found.push(Lint::Crossing { curves: (curve_a, curve_b), at });
+            }
+        }
+    }
+    found
+}
+
+fn intersect(a0: Vec2, a1: Vec2, b0: Vec2, b1: Vec2) -> Option<Vec2> {
+    let (a, b) = (a1 - a0, b1 - b0);
+    let denom = a.perp_dot(b);
+    if denom.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let t = (b0 - a0).perp_dot(b) / denom;
+    let u = (b0 - a0).perp_dot(a) / denom;
+    if (0.0..1.0).contains(&t) && (0.0..1.0).contains(&u) {
+        Some(a0 + a * t)
+    } else {
+        None
+    }
+}
@@ -1,7 +1,8 @@
 use glam::{vec2, vec3, Mat4, Vec2, Vec3};
 use miniquad::*;
 
-use train::art::{Art, ArtData};
+use train::art::{Art, ArtData, Track, TrackStyle, UvMap};
+use train::generate::{generate, Layout};
 
 mod render;
 
@@ -10,99 +11,248 @@ mod render;
 /// train cars as a Vec<Car>.
 mod cars;
 
-fn read_art_data() -> Box<ArtData> {
-    use std::io::Read;
-    const SIZE: usize = std::mem::size_of::<ArtData>();
+/// Dragging the track's points around, live, from inside the game.
+mod editor;
 
-    let mut data_bytes = Box::new([0; SIZE]);
-    std::fs::File::open("train.cedset").unwrap().read_exact(data_bytes.as_mut()).unwrap();
+/// Driving the train from the keyboard, with a throttle, brake and reverser.
+mod controls;
 
-    unsafe {
-        unsafe fn transmute_copy_boxed<T, U>(src: &T) -> Box<U> {
-            let src = src as *const T as *const U;
-            let layout = std::alloc::Layout::new::<U>();
-            let dst = std::alloc::alloc(layout) as *mut U;
+/// Trains that drive themselves, keeping to the speed limits and stopping where they're told.
+mod autopilot;
 
-            if dst.is_null() {
-                std::alloc::handle_alloc_error(layout)
-            } else {
-                src.copy_to(dst, 1);
-                Box::from_raw(dst)
-            }
-        }
-
-        transmute_copy_boxed::<[u8; SIZE], ArtData>(data_bytes.as_ref())
-    }
+/// What there is to draw on top of everything else, flat on the screen.
+#[derive(Debug, Clone, Copy)]
+enum Tile {
+    Lit,
+    Unlit,
 }
 
 #[derive(Default, Debug)]
-struct RenderQueue(Vec<(Art, Mat4)>);
+struct RenderQueue {
+    art: Vec<(Art, Mat4)>,
+    /// Made from the rod `ArtData::make_rod` builds.
+    rods: Vec<Mat4>,
+    /// Placed in pixels, from the top left of the screen.
+    hud: Vec<(Tile, Mat4)>,
+}
 impl RenderQueue {
     fn draw_mat4(&mut self, art: Art, mat: Mat4) {
-        self.0.push((art, mat));
+        self.art.push((art, mat));
     }
 
-    fn draw(&mut self, art: Art, pos: Vec2, rot: Rot) {
-        use std::f32::consts::FRAC_PI_2;
+    fn draw_rod(&mut self, mat: Mat4) {
+        self.rods.push(mat);
+    }
 
-        self.draw_mat4(
-            art,
-            Mat4::from_translation(vec3(pos.x, 0.0, pos.y))
-                * Mat4::from_rotation_y(FRAC_PI_2 - rot.0),
-        )
+    fn draw_tile(&mut self, tile: Tile, mat: Mat4) {
+        self.hud.push((tile, mat));
     }
 
     fn clear_draws(&mut self) {
-        self.0.clear();
+        self.art.clear();
+        self.rods.clear();
+        self.hud.clear();
     }
 }
 
+/// How far the polyline the cars run along may stray from the real curves.
+/// Much tighter than `TrackStyle::tolerance`, since cars cutting corners is
+/// far easier to notice than a few too few sleepers' worth of rail geometry.
+const TRACK_TOLERANCE: f32 = 0.002;
+
+/// How many seconds of simulation every step covers. However quickly frames are drawn,
+/// the simulation always moves in steps exactly this long.
+const TIMESTEP: f32 = 1.0 / 120.0;
+
+/// How many seconds the tile for something that's happened stays lit.
+const ALERT_TIME: f32 = 2.0;
+
 struct Stage {
     mouse_pos: Vec2,
     mouse_on_ground: Vec3,
     cam_origin: Vec3,
     cam_offset: Vec3,
+    art_data: Box<ArtData>,
+    /// How many vertices and indices the art takes up, before the track comes after it.
+    art_len: (u32, u32),
+    track_curves: Track,
     track: Vec<Vec2>,
+    /// How far along `track` each of its points is.
+    track_dists: Vec<f32>,
+    speed_limits: Vec<autopilot::SpeedLimit>,
+    track_style: TrackStyle,
     renderer: render::Renderer,
     render_queue: RenderQueue,
-    train: cars::Cars,
+    trains: Vec<cars::Cars>,
+    /// Which of the trains the camera follows.
+    followed: usize,
+    /// The pairs of trains that have run into each other, see `Collision::touching`.
+    touching: Vec<(usize, usize)>,
+    shells: cars::Shells,
+    /// What's happened to the trains since the last frame.
+    events: Vec<cars::Event>,
+    /// How many seconds more each of the tiles for what's happened stays lit, see `Event::alert`.
+    alerts: [f32; 4],
+    /// The levers of the train the camera follows, which is the one being driven.
+    controls: controls::Controls,
+    bindings: controls::Bindings,
+    editor: Option<editor::Editor>,
+    /// When `update` last ran, in seconds.
+    last_update: f64,
+    /// Time that's passed which the simulation has yet to step through, in seconds.
+    unsimulated: f32,
 }
 
 impl Stage {
     fn new(ctx: &mut Context) -> Self {
-        let mut art_data = read_art_data();
-        let track = art_data.make_track();
+        let mut art_data = ArtData::read("train.cedset").unwrap();
+        // The tiles and the rod go in before the track does, so they're kept when it's rebuilt.
+        let tiles =
+            [art_data.make_tile(UvMap::solid(2, 0)), art_data.make_tile(UvMap::solid(4, 0))];
+        let rod = art_data.make_rod(UvMap::solid(2, 2));
 
-        Stage {
+        let trains = {
+            let consists = std::fs::read_to_string("consists.txt").unwrap();
+            let trains = cars::Roster::parse(&consists).and_then(|roster| match consist_name() {
+                Some(name) => Ok(vec![roster.build(&name, 0.0, false)?]),
+                None => roster.trains(),
+            });
+            trains.unwrap_or_else(|e| panic!("couldn't read consists.txt: {}", e))
+        };
+
+        let mut stage = Stage {
             mouse_pos: Vec2::from(ctx.screen_size()) / 2.0,
             mouse_on_ground: Vec3::zero(),
             cam_offset: Vec3::zero(),
             cam_origin: Vec3::zero(),
-            renderer: render::Renderer::new(ctx, art_data),
-            render_queue: RenderQueue(Vec::with_capacity(1000)),
-            train: cars::Cars::default(),
-            track,
+            renderer: render::Renderer::new(ctx, &art_data, tiles, rod),
+            render_queue: RenderQueue {
+                art: Vec::with_capacity(1000),
+                rods: Vec::with_capacity(100),
+                hud: Vec::with_capacity(100),
+            },
+            controls: controls::Controls::of(&trains[0]),
+            trains,
+            followed: 0,
+            touching: vec![],
+            shells: cars::Shells::default(),
+            events: vec![],
+            alerts: [0.0; 4],
+            bindings: match std::fs::read_to_string("controls.txt") {
+                Ok(text) => controls::Bindings::parse(&text)
+                    .unwrap_or_else(|e| panic!("couldn't read controls.txt: {}", e)),
+                Err(_) => controls::Bindings::default(),
+            },
+            art_len: (art_data.last_occupied_vert, art_data.last_occupied_index),
+            track_curves: match random_map_seed() {
+                Some(seed) => Track::from_points(&generate(&Layout { seed, ..Default::default() })),
+                None => art_data.track.to_track(),
+            },
+            track: vec![],
+            track_dists: vec![],
+            speed_limits: vec![],
+            track_style: TrackStyle::default(),
+            editor: None,
+            last_update: date::now(),
+            unsimulated: 0.0,
+            art_data,
+        };
+        stage.rebuild_track(ctx);
+        stage
+    }
+
+    /// Throws away the old track geometry and makes it anew from `track_curves`,
+    /// along with the editor's markers if it's open.
+    fn rebuild_track(&mut self, ctx: &mut Context) {
+        let (verts, indices) = self.art_len;
+        self.art_data.last_occupied_vert = verts;
+        self.art_data.last_occupied_index = indices;
+
+        if let Err(e) = self.art_data.make_track(&self.track_curves, &self.track_style) {
+            panic!("couldn't build the track: {}", e);
         }
+        self.track = self.track_curves.tessellate(TRACK_TOLERANCE);
+        let mut so_far = 0.0;
+        self.track_dists = (0..self.track.len())
+            .map(|i| {
+                so_far += self.track[i].distance(self.track[i.saturating_sub(1)]);
+                so_far
+            })
+            .collect();
+        self.speed_limits = autopilot::SpeedLimit::along(&self.track_curves);
+
+        let gizmos = match &self.editor {
+            Some(editor) => Some(self.art_data.make_gizmos(&editor.points, editor.selected)),
+            None => None,
+        };
+        self.renderer.update_geometry(ctx, &self.art_data, gizmos);
+    }
+
+    /// Moves everything in the game along by one `TIMESTEP`.
+    fn step(&mut self) {
+        // The trains stand still while the track is being edited.
+        let dt = if self.editor.is_some() { 0.0 } else { TIMESTEP };
+        let loop_len = if self.track_curves.is_loop() { Some(self.track_len()) } else { None };
+        for train in &mut self.trains {
+            if let Some(mut autopilot) = train.autopilot.take() {
+                autopilot.drive(dt, train, &self.speed_limits, loop_len);
+                train.autopilot = Some(autopilot);
+            }
+        }
+        self.controls.step(dt, &mut self.trains[self.followed]);
+        for train in &mut self.trains {
+            // The track is laid flat, for now.
+            train.step(dt, |_| 0.0);
+        }
+        self.stop_at_ends();
+        self.step_derailments(dt);
+        self.handle_contacts();
+        self.aim_guns(dt);
+        self.step_shells(dt);
+    }
+
+    /// Points the camera at train `i`, and hands its controls over to the driver.
+    fn follow(&mut self, i: usize) {
+        self.followed = i;
+        self.controls = controls::Controls::of(&self.trains[i]);
     }
 
     fn eye_pos(&self) -> Vec3 {
         self.cam_origin + self.cam_offset
     }
 
+    fn track_len(&self) -> f32 {
+        self.track_dists.last().copied().unwrap_or(0.0)
+    }
+
+    /// Where on the ground `distance` along the track is. Off the ends of a track that
+    /// isn't a loop, it's wherever the nearest end is.
     fn track_point(&self, distance: f32) -> Vec2 {
-        let mut so_far = 0.0;
-        for pair in self.track.windows(2) {
-            if let &[left, right] = pair {
-                let len = (left - right).length();
-                if distance < so_far + len {
-                    return left.lerp(right, (distance - so_far) / len);
-                } else {
-                    so_far += len;
-                }
-            }
+        let len = self.track_len();
+        let distance =
+            if self.track_curves.is_loop() { distance.rem_euclid(len) } else { distance };
+        match (self.track.first(), self.track.last()) {
+            (Some(&first), _) if distance <= 0.0 => return first,
+            (_, Some(&last)) if distance >= len => return last,
+            (None, _) => return Vec2::zero(),
+            _ => {}
         }
 
-        panic!("no points?");
+        let i = self.track_dists.partition_point(|&d| d <= distance).max(1) - 1;
+        let i = i.min(self.track.len() - 2);
+        let (from, to) = (self.track_dists[i], self.track_dists[i + 1]);
+        self.track[i].lerp(self.track[i + 1], (distance - from) / (to - from))
+    }
+
+    /// Which way the track heads at `distance` along it. Off the ends of a track that
+    /// isn't a loop, it's the way it was heading at the nearest end.
+    fn track_heading(&self, distance: f32) -> Vec2 {
+        let distance = if self.track_curves.is_loop() {
+            distance
+        } else {
+            distance.max(0.25).min(self.track_len() - 0.25)
+        };
+        (self.track_point(distance + 0.25) - self.track_point(distance - 0.25)).normalize()
     }
 }
 
@@ -115,10 +265,39 @@ impl EventHandler for Stage {
         self.mouse_on_ground =
             line_plane_intersect(eye_pos, eye_pos - out, Vec3::zero(), Vec3::unit_y());
 
+        // A long stall, say from dragging the window around, is skipped
+        // rather than caught up on all at once.
+        let now = date::now();
+        let elapsed = (now - self.last_update).min(0.25) as f32;
+        self.unsimulated += elapsed;
+        self.last_update = now;
+        self.events.clear();
+        while self.unsimulated >= TIMESTEP {
+            self.step();
+            self.unsimulated -= TIMESTEP;
+        }
+        for alert in &mut self.alerts {
+            *alert = (*alert - elapsed).max(0.0);
+        }
+        for event in &self.events {
+            self.alerts[event.alert()] = ALERT_TIME;
+        }
+        let blend = self.unsimulated / TIMESTEP;
+
         let mut rq = std::mem::take(&mut self.render_queue);
         rq.clear_draws();
-        self.draw_train(&mut rq);
+        self.draw_trains(&mut rq, blend);
+        self.draw_shells(&mut rq, blend);
+        let screen = Vec2::from(ctx.screen_size());
+        self.controls.draw(&mut rq, screen, &self.trains[self.followed]);
+        self.draw_alerts(&mut rq);
+        if let Some(editor) = &self.editor {
+            editor.draw(&mut rq, screen);
+        }
         self.render_queue = rq;
+        self.follow_train(blend);
+
+        self.update_editor(ctx);
     }
 
     fn draw(&mut self, ctx: &mut Context) {
@@ -132,6 +311,40 @@ impl EventHandler for Stage {
     fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32) {
         self.mouse_pos = vec2(x, y);
     }
+
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, _: f32, _: f32) {
+        if button == MouseButton::Left {
+            if self.editor.is_none() {
+                self.fire_guns();
+            }
+            self.editor_grab(ctx);
+        }
+    }
+
+    fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, _: f32, _: f32) {
+        if button == MouseButton::Left {
+            self.editor_release();
+        }
+    }
+
+    fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, mods: KeyMods, repeat: bool) {
+        if !repeat {
+            match (keycode, self.bindings.action(keycode)) {
+                _ if self.editor.is_some() => {}
+                (_, Some(action)) => self.controls.press(action, &mut self.trains[self.followed]),
+                (KeyCode::C, _) => self.follow((self.followed + 1) % self.trains.len()),
+                (KeyCode::U, _) => self.drop_last_car(),
+                _ => {}
+            }
+            self.editor_key_down(ctx, keycode, mods);
+        }
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _: KeyMods) {
+        if let Some(action) = self.bindings.action(keycode) {
+            self.controls.release(action);
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -183,6 +396,27 @@ fn line_plane_intersect(
     line_origin + line * d
 }
 
+/// `--random-map` swaps the track in the cedset out for a made up one.
+/// It can be followed by the seed to make it from, otherwise the time is used.
+fn random_map_seed() -> Option<u64> {
+    let args: Vec<String> = std::env::args().collect();
+    let i = args.iter().position(|arg| arg == "--random-map")?;
+    let seed = args.get(i + 1).and_then(|seed| seed.parse().ok()).unwrap_or_else(|| {
+        use std::time::{SystemTime, UNIX_EPOCH};
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    });
+    println!("random map seed: {}", seed);
+    Some(seed)
+}
+
+/// `--consist` starts off with just one train of the consist in `consists.txt` it names,
+/// rather than the trains listed there.
+fn consist_name() -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    let i = args.iter().position(|arg| arg == "--consist")?;
+    Some(args.get(i + 1).expect("--consist needs the name of one").clone())
+}
+
 fn main() {
     miniquad::start(conf::Conf { sample_count: 4, ..conf::Conf::default() }, |mut ctx| {
         UserData::owning(Stage::new(&mut ctx), ctx)
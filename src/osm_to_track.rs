@@ -0,0 +1,395 @@
+//! Turns the railway lines in an OpenStreetMap extract, an `.osm` file saved from the
+//! "Export" button on openstreetmap.org or cut out with osmium, into a `track.json`.
+//!
+//! Every way tagged `railway=rail` is kept. Ways are joined up where they share an end node,
+//! and split wherever a node is shared by more than two of them, so each track in the output
+//! runs from one junction or dead end to the next. The tracks meeting at a junction start or
+//! end on exactly the same spot, and leave it heading the same way as the straightest track
+//! across from them, the way the tracks through a real turnout do.
+//!
+//! Positions come out in metres, with x pointing east and y pointing north from the middle
+//! of all the railway in the file.
+use glam::{vec2, Vec2};
+use std::collections::HashMap;
+use train::art::{ArtData, BezierPoint, Track, TrackStyle, TRACK_CURVE_COUNT};
+
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// The most a track can turn at a junction and still count as going straight through it.
+const MAX_THROUGH_ANGLE: f32 = 45.0;
+
+/// One `<tag ...>` out of the XML, without its children.
+struct Element<'a> {
+    name: &'a str,
+    attributes: &'a str,
+}
+
+impl<'a> Element<'a> {
+    fn get(&self, key: &str) -> Option<&'a str> {
+        let mut rest = self.attributes;
+        while let Some(at) = rest.find(key) {
+            let preceded_by_space = at == 0 || rest[..at].ends_with(char::is_whitespace);
+            let value = rest[at + key.len()..].trim_start();
+            rest = &rest[at + key.len()..];
+            if !preceded_by_space || !value.starts_with('=') {
+                continue;
+            }
+
+            let value = value[1..].trim_start();
+            let quote = value.chars().next().filter(|&c| c == '"' || c == '\'')?;
+            return value[1..].find(quote).map(|len| &value[1..1 + len]);
+        }
+        None
+    }
+
+    fn id(&self, key: &str) -> u64 {
+        let value = self.get(key).unwrap_or_else(|| panic!("a <{}> has no {}", self.name, key));
+        value.parse().unwrap_or_else(|_| panic!("{} isn't an id", value))
+    }
+}
+
+/// Every element in `xml`, opening and closing tags alike, skipping comments and the like.
+fn elements(xml: &str) -> impl Iterator<Item = Element<'_>> {
+    xml.split('<').skip(1).filter_map(|tag| {
+        let tag = &tag[..tag.find('>')?];
+        if tag.starts_with(&['?', '!'][..]) {
+            return None;
+        }
+        // Closing tags keep their slash, so `</way>` comes out named `/way`.
+        let name_len =
+            tag[1..].find(|c: char| c.is_whitespace() || c == '/').map_or(tag.len(), |len| len + 1);
+        Some(Element { name: &tag[..name_len], attributes: &tag[name_len..] })
+    })
+}
+
+/// The railway in an `.osm` file: where each node is, and
+/// the nodes each `railway=rail` way runs through, in order.
+struct Railway {
+    nodes: HashMap<u64, (f64, f64)>,
+    ways: Vec<Vec<u64>>,
+}
+
+fn read_osm(xml: &str) -> Railway {
+    let mut nodes = HashMap::new();
+    let mut ways = vec![];
+    // The way being read, and whether it's turned out to be a railway yet.
+    let mut way: Option<(Vec<u64>, bool)> = None;
+
+    for element in elements(xml) {
+        match element.name {
+            "node" => {
+                let lat_lon = (element.get("lat"), element.get("lon"));
+                if let (Some(lat), Some(lon)) = lat_lon {
+                    let parse =
+                        |n: &str| n.parse().unwrap_or_else(|_| panic!("{} isn't a number", n));
+                    nodes.insert(element.id("id"), (parse(lat), parse(lon)));
+                }
+            }
+            "way" => way = Some((vec![], false)),
+            "nd" => {
+                if let Some((refs, _)) = &mut way {
+                    refs.push(element.id("ref"));
+                }
+            }
+            "tag" => {
+                if let Some((_, rail)) = &mut way {
+                    *rail |=
+                        element.get("k") == Some("railway") && element.get("v") == Some("rail");
+                }
+            }
+            "/way" => {
+                if let Some((refs, true)) = way.take() {
+                    ways.push(refs);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // An extract cut out of a bigger map can leave ways running off to nodes it doesn't have.
+    let ways = ways
+        .iter()
+        .flat_map(|way| way.split(|id| !nodes.contains_key(id)))
+        .filter(|way| way.len() > 1)
+        .map(|way| way.to_vec())
+        .collect();
+    Railway { nodes, ways }
+}
+
+impl Railway {
+    /// Lays the nodes out flat, in metres from the middle of the railway.
+    /// Close enough to right over the few kilometres a map extract covers.
+    fn project(&self) -> HashMap<u64, Vec2> {
+        let used = || self.ways.iter().flatten().map(|id| self.nodes[id]);
+        let (min, max) = used().fold(
+            ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN)),
+            |((min_lat, min_lon), (max_lat, max_lon)), (lat, lon)| {
+                ((min_lat.min(lat), min_lon.min(lon)), (max_lat.max(lat), max_lon.max(lon)))
+            },
+        );
+        let (lat0, lon0) = ((min.0 + max.0) / 2.0, (min.1 + max.1) / 2.0);
+
+        let metres_per_degree = EARTH_RADIUS * std::f64::consts::PI / 180.0;
+        self.ways
+            .iter()
+            .flatten()
+            .map(|&id| {
+                let (lat, lon) = self.nodes[&id];
+                let x = (lon - lon0) * metres_per_degree * lat0.to_radians().cos();
+                let y = (lat - lat0) * metres_per_degree;
+                (id, vec2(x as f32, y as f32))
+            })
+            .collect()
+    }
+
+    /// The railway cut up into lines that each run from one junction or dead end to the next,
+    /// or all the way around a loop and back to where they started.
+    fn lines(&self) -> Vec<Vec<u64>> {
+        // How many pieces of track leave each node.
+        let mut degree: HashMap<u64, usize> = HashMap::new();
+        for way in &self.ways {
+            for (i, id) in way.iter().enumerate() {
+                let ends = i == 0 || i == way.len() - 1;
+                *degree.entry(*id).or_default() += if ends { 1 } else { 2 };
+            }
+        }
+
+        // Ways split at every junction part way along them.
+        let mut pieces: Vec<Vec<u64>> = vec![];
+        for way in &self.ways {
+            let mut piece = vec![way[0]];
+            for &id in &way[1..] {
+                piece.push(id);
+                if degree[&id] != 2 {
+                    pieces.push(std::mem::replace(&mut piece, vec![id]));
+                }
+            }
+            if piece.len() > 1 {
+                pieces.push(piece);
+            }
+        }
+
+        let mut ends: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (i, piece) in pieces.iter().enumerate() {
+            ends.entry(piece[0]).or_default().push(i);
+            ends.entry(piece[piece.len() - 1]).or_default().push(i);
+        }
+        // The piece carrying on from `from` at `node`, if that's where two ways just meet.
+        let carry_on = |node: u64, from: usize| {
+            let touching = &ends[&node];
+            if degree[&node] != 2 || touching.len() != 2 {
+                return None;
+            }
+            Some(if touching[0] == from { touching[1] } else { touching[0] })
+        };
+
+        let mut used = vec![false; pieces.len()];
+        let mut lines = vec![];
+        for first in 0..pieces.len() {
+            if used[first] {
+                continue;
+            }
+            used[first] = true;
+            let mut line = pieces[first].clone();
+
+            // Carry on forwards as far as possible, and then backwards.
+            for _ in 0..2 {
+                let mut last = first;
+                while let Some(next) = carry_on(line[line.len() - 1], last) {
+                    if used[next] {
+                        break;
+                    }
+                    used[next] = true;
+                    let mut piece = pieces[next].clone();
+                    if piece[0] != line[line.len() - 1] {
+                        piece.reverse();
+                    }
+                    line.extend_from_slice(&piece[1..]);
+                    last = next;
+                }
+                line.reverse();
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+/// Drops every point that's within `tolerance` of the line the points either side of it
+/// make, which is most of them along a straight. The ends are always kept.
+fn simplify(points: &[Vec2], tolerance: f32) -> Vec<Vec2> {
+    fn keep(points: &[Vec2], tolerance: f32, kept: &mut Vec<Vec2>) {
+        let (first, last) = (points[0], points[points.len() - 1]);
+        let off = |p: Vec2| {
+            if first == last {
+                p.distance(first)
+            } else {
+                (p - first).perp_dot((last - first).normalize()).abs()
+            }
+        };
+
+        let furthest = (1..points.len() - 1)
+            .map(|i| (i, off(points[i])))
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+        if let Some((i, _)) = furthest.filter(|&(_, dist)| dist > tolerance) {
+            keep(&points[..=i], tolerance, kept);
+            kept.pop();
+            keep(&points[i..], tolerance, kept);
+        } else {
+            kept.extend_from_slice(&[first, last]);
+        }
+    }
+
+    let mut kept = vec![];
+    keep(points, tolerance, &mut kept);
+    kept
+}
+
+/// Smooth curves through `points`, with each point's handles lined up along the
+/// line between the points either side of it. `start` and `end` are which way the
+/// ends of an open line head, if something else decides that.
+fn fit(points: &[Vec2], start: Option<Vec2>, end: Option<Vec2>) -> Vec<BezierPoint> {
+    let last = points.len() - 1;
+    let looped = points[0].distance(points[last]) < 0.001;
+
+    (0..=last)
+        .map(|i| {
+            let pos = points[i];
+            let prev = if i > 0 {
+                points[i - 1]
+            } else if looped {
+                points[last - 1]
+            } else {
+                pos
+            };
+            let next = if i < last {
+                points[i + 1]
+            } else if looped {
+                points[1]
+            } else {
+                pos
+            };
+            let heading = match (i, looped) {
+                (0, false) => start.unwrap_or(next - pos),
+                (i, false) if i == last => end.unwrap_or(pos - prev),
+                _ => next - prev,
+            };
+            // A loop of only two points has the same one either side of each of them.
+            let heading = if heading == Vec2::zero() { next - pos } else { heading }.normalize();
+
+            // Open ends have nothing on one side, so mirror the handle on the other.
+            let left = if prev == pos { next.distance(pos) } else { prev.distance(pos) };
+            let right = if next == pos { prev.distance(pos) } else { next.distance(pos) };
+            BezierPoint {
+                left: (pos - heading * left / 3.0).into(),
+                pos: pos.into(),
+                right: (pos + heading * right / 3.0).into(),
+            }
+        })
+        .collect()
+}
+
+fn main() {
+    let mut input = None;
+    let mut output = "track.json".to_string();
+    let mut tolerance = 0.5;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tolerance" => {
+                let value = args.next().expect("--tolerance needs a value");
+                tolerance = value.parse().unwrap_or_else(|_| panic!("{} isn't a number", value));
+            }
+            "--out" => output = args.next().expect("--out needs a file name"),
+            _ if input.is_none() => input = Some(arg),
+            other => panic!("unknown argument {}", other),
+        }
+    }
+    let input = input
+        .expect("usage: osm-to-track <extract.osm> [--tolerance <metres>] [--out <track.json>]");
+
+    let railway = read_osm(&std::fs::read_to_string(&input).unwrap());
+    if railway.ways.is_empty() {
+        panic!("no railway=rail ways in {}", input);
+    }
+    let positions = railway.project();
+
+    let lines: Vec<(u64, Vec<Vec2>, u64)> = railway
+        .lines()
+        .iter()
+        .map(|line| {
+            let points: Vec<Vec2> = line.iter().map(|id| positions[id]).collect();
+            (line[0], simplify(&points, tolerance), line[line.len() - 1])
+        })
+        .collect();
+
+    // Where each junction's lines go to first after leaving it.
+    let mut leaving: HashMap<u64, Vec<Vec2>> = HashMap::new();
+    for (start, points, end) in &lines {
+        leaving.entry(*start).or_default().push(points[1]);
+        leaving.entry(*end).or_default().push(points[points.len() - 2]);
+    }
+    // Which way a line leaving `junction` towards `towards` should start off, so
+    // that it carries straight on from the line across from it, if there is one.
+    let through = |junction: u64, towards: Vec2| {
+        let at = positions[&junction];
+        let (heading, back) = ((towards - at).normalize(), &leaving[&junction]);
+        back.iter()
+            .map(|&from| (at - from).normalize())
+            .filter(|&across| across.angle_between(heading).to_degrees() < MAX_THROUGH_ANGLE)
+            .max_by(|a, b| a.dot(heading).partial_cmp(&b.dot(heading)).unwrap())
+            .map(|across| (across + heading).normalize())
+    };
+
+    // Every track has to fit in the cedset after the art.
+    let art = ArtData::read("train.cedset").unwrap_or_else(|_| {
+        println!("no train.cedset yet, so tracks are only measured against an empty one");
+        Box::new(ArtData::default())
+    });
+    let style = TrackStyle::default();
+
+    let mut tracks = vec![];
+    for (start, points, end) in &lines {
+        let (first, last) = (points[1], points[points.len() - 2]);
+        let track = fit(points, through(*start, first), through(*end, last).map(|h| -h));
+        println!("track {}: {} points, {:.0} metres", tracks.len(), track.len(), {
+            points.windows(2).map(|w| w[0].distance(w[1])).sum::<f32>()
+        });
+
+        // Tracks too long to build are cut into pieces that carry on from each other.
+        let (curves, mut from, mut pieces) = (track.len() - 1, 0, vec![]);
+        while from < curves {
+            let fits = |to: usize| art.fits_track(&Track::from_points(&track[from..=to]), &style);
+            if !fits(from + 1) {
+                panic!("curve {} of track {} is too big to build", from, tracks.len());
+            }
+            // The furthest the piece can go and still fit, found by halving.
+            let (mut low, mut high) = (from + 1, (from + TRACK_CURVE_COUNT).min(curves));
+            while low < high {
+                let mid = high - (high - low) / 2;
+                if fits(mid) {
+                    low = mid;
+                } else {
+                    high = mid - 1;
+                }
+            }
+            pieces.push(track[from..=low].to_vec());
+            from = low;
+        }
+        if pieces.len() > 1 {
+            println!("  too big for one track, so it's split into {} of them", pieces.len());
+        }
+        tracks.extend(pieces);
+    }
+
+    for (id, leaving) in &leaving {
+        if leaving.len() > 2 {
+            let Vec2 { x, y } = positions[id];
+            println!("junction at ({:.1}, {:.1}): {} tracks meet", x, y, leaving.len());
+        }
+    }
+
+    std::fs::write(&output, BezierPoint::to_json(&tracks)).unwrap();
+    println!("wrote {}", output);
+}
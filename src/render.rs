@@ -7,12 +7,30 @@ pub struct Renderer {
     bindings: Bindings,
     art_indices: ArtIndices,
     proj: Mat4,
+    /// Maps pixels from the top left of the screen, for the display drawn over the top.
+    hud_proj: Mat4,
+    /// Where each `Tile` is, in the order they're listed.
+    tile_indices: [(i32, i32); 2],
+    rod_indices: (i32, i32),
     track_indices: (i32, i32),
+    gizmo_indices: Option<(i32, i32)>,
 }
 impl Renderer {
-    pub fn new(ctx: &mut Context, art_data: Box<ArtData>) -> Self {
-        let vertex_buffer = Buffer::immutable(ctx, BufferType::VertexBuffer, &art_data.vertices);
-        let index_buffer = Buffer::immutable(ctx, BufferType::IndexBuffer, &art_data.indices);
+    /// The buffers are streamed, rather than immutable, so that the track can be
+    /// rebuilt while it's being edited.
+    pub fn new(
+        ctx: &mut Context,
+        art_data: &ArtData,
+        tile_indices: [(i32, i32); 2],
+        rod_indices: (i32, i32),
+    ) -> Self {
+        let vertex_buffer = Buffer::stream(
+            ctx,
+            BufferType::VertexBuffer,
+            std::mem::size_of_val(&art_data.vertices),
+        );
+        let index_buffer =
+            Buffer::stream(ctx, BufferType::IndexBuffer, std::mem::size_of_val(&art_data.indices));
 
         let texture = Texture::from_rgba8(ctx, 16, 16, &art_data.image);
         texture.set_filter(ctx, FilterMode::Nearest);
@@ -41,23 +59,51 @@ impl Renderer {
             },
         );
 
-        Renderer {
+        let mut renderer = Renderer {
             pipeline,
             bindings,
             proj: proj(ctx),
+            hud_proj: hud_proj(ctx),
+            tile_indices,
+            rod_indices,
             art_indices: art_data.art_indices,
             track_indices: art_data.track_indices,
-        }
+            gizmo_indices: None,
+        };
+        renderer.update_geometry(ctx, art_data, None);
+        renderer
+    }
+
+    /// Uploads the geometry `art_data` holds now, like after the track's been rebuilt.
+    pub fn update_geometry(
+        &mut self,
+        ctx: &mut Context,
+        art_data: &ArtData,
+        gizmo_indices: Option<(i32, i32)>,
+    ) {
+        let vertices = &art_data.vertices[..art_data.last_occupied_vert as usize];
+        let indices = &art_data.indices[..art_data.last_occupied_index as usize];
+        self.bindings.vertex_buffers[0].update(ctx, vertices);
+        self.bindings.index_buffer.update(ctx, indices);
+
+        self.track_indices = art_data.track_indices;
+        self.gizmo_indices = gizmo_indices;
     }
 
     pub fn resize(&mut self, ctx: &mut Context) {
         self.proj = proj(ctx);
+        self.hud_proj = hud_proj(ctx);
     }
 }
 
 fn proj(ctx: &mut Context) -> Mat4 {
     let (width, height) = ctx.screen_size();
-    Mat4::perspective_rh_gl(45.0f32.to_radians(), width / height, 0.01, 250.0)
+    Mat4::perspective_rh_gl(45.0f32.to_radians(), width / height, 0.01, 500.0)
+}
+
+fn hud_proj(ctx: &mut Context) -> Mat4 {
+    let (width, height) = ctx.screen_size();
+    Mat4::orthographic_rh_gl(0.0, width, height, 0.0, -1.0, 1.0)
 }
 
 impl super::Stage {
@@ -83,14 +129,36 @@ impl super::Stage {
             ctx.apply_uniforms(&uni);
             let (start, num) = renderer.track_indices;
             ctx.draw(start, num, 1);
+
+            if let Some((start, num)) = renderer.gizmo_indices {
+                ctx.draw(start, num, 1);
+            }
         }
-        for &(art, model) in &self.render_queue.0 {
+        for &(art, model) in &self.render_queue.art {
             uni.set_model(model);
             ctx.apply_uniforms(&uni);
 
             let (start, num) = renderer.art_indices.indices(art);
             ctx.draw(start, num, 1);
         }
+        for &model in &self.render_queue.rods {
+            uni.set_model(model);
+            ctx.apply_uniforms(&uni);
+
+            let (start, num) = renderer.rod_indices;
+            ctx.draw(start, num, 1);
+        }
+
+        // The display goes over everything, however close it is.
+        ctx.clear(None, Some(1.0), None);
+        uni.view_proj = renderer.hud_proj;
+        for &(tile, model) in &self.render_queue.hud {
+            uni.set_model(model);
+            ctx.apply_uniforms(&uni);
+
+            let (start, num) = renderer.tile_indices[tile as usize];
+            ctx.draw(start, num, 1);
+        }
         ctx.end_render_pass();
 
         ctx.commit_frame();
@@ -0,0 +1,267 @@
+//! Pulls the `<path>`s out of an SVG, say one drawn in Inkscape, and writes them out
+//! as a `track.json` for `gltf-to-cedset` to pick up. Every subpath becomes a track of
+//! its own, and subpaths closed with a `Z` end on top of their first point, making a loop.
+//!
+//! Only `M`, `L`, `H`, `V`, `C`, `S` and `Z` (and their relative versions) are understood,
+//! and `transform`s are ignored, so flatten those away before saving the SVG.
+use glam::{vec2, Vec2};
+use train::art::BezierPoint;
+
+/// A cubic Bezier piece of a path, starting wherever the last one ended.
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    left: Vec2,
+    right: Vec2,
+    end: Vec2,
+}
+
+struct Subpath {
+    start: Vec2,
+    segments: Vec<Segment>,
+    closed: bool,
+}
+
+impl Subpath {
+    fn new(start: Vec2) -> Self {
+        Self { start, segments: vec![], closed: false }
+    }
+
+    fn end(&self) -> Vec2 {
+        self.segments.last().map_or(self.start, |s| s.end)
+    }
+
+    fn line_to(&mut self, to: Vec2) {
+        let from = self.end();
+        self.segments.push(Segment {
+            left: from.lerp(to, 1.0 / 3.0),
+            right: from.lerp(to, 2.0 / 3.0),
+            end: to,
+        });
+    }
+
+    /// The handles at either end of an open path, which no curve uses,
+    /// mirror the ones across from them.
+    fn points(&self, scale: f32) -> Vec<BezierPoint> {
+        let point = |left: Vec2, pos: Vec2, right: Vec2| BezierPoint {
+            left: (left * scale).into(),
+            pos: (pos * scale).into(),
+            right: (right * scale).into(),
+        };
+        let (first, last) = (self.segments[0], self.segments[self.segments.len() - 1]);
+
+        let start_left = if self.closed { last.right } else { self.start * 2.0 - first.left };
+        let mut points = vec![point(start_left, self.start, first.left)];
+        for (i, segment) in self.segments.iter().enumerate() {
+            let right = match self.segments.get(i + 1) {
+                Some(next) => next.left,
+                None if self.closed => first.left,
+                None => segment.end * 2.0 - segment.right,
+            };
+            points.push(point(segment.right, segment.end, right));
+        }
+        points
+    }
+}
+
+fn parse_path(d: &str) -> Result<Vec<Subpath>, String> {
+    let mut tokens = Tokens(d);
+    let mut subpaths: Vec<Subpath> = vec![];
+    let mut command = None;
+    let mut current = Vec2::zero();
+    // Where an `S` mirrors its first handle from, if the last command was a curve.
+    let mut last_handle: Option<Vec2> = None;
+
+    loop {
+        if let Some(c) = tokens.command() {
+            command = Some(c);
+        } else if tokens.at_end() {
+            break;
+        }
+        let c = command.ok_or("path data doesn't start with a command")?;
+        let relative = c.is_ascii_lowercase();
+        let origin = if relative { current } else { Vec2::zero() };
+        let point = |tokens: &mut Tokens| -> Result<Vec2, String> {
+            Ok(origin + vec2(tokens.number()?, tokens.number()?))
+        };
+
+        match c.to_ascii_uppercase() {
+            'M' => {
+                current = point(&mut tokens)?;
+                subpaths.push(Subpath::new(current));
+                // Any more points after a move are lines.
+                command = Some(if relative { 'l' } else { 'L' });
+                last_handle = None;
+                continue;
+            }
+            'Z' => {
+                let subpath = subpaths.last_mut().ok_or("Z before any M")?;
+                if subpath.end().distance(subpath.start) > 0.001 {
+                    subpath.line_to(subpath.start);
+                }
+                subpath.closed = true;
+                current = subpath.start;
+                // A path can carry on after a Z without a new M.
+                let start = subpath.start;
+                command = None;
+                last_handle = None;
+                if matches!(tokens.peek_command(), Some(c) if !c.eq_ignore_ascii_case(&'M')) {
+                    subpaths.push(Subpath::new(start));
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let subpath = subpaths.last_mut().ok_or("drawing before any M")?;
+        match c.to_ascii_uppercase() {
+            'L' => {
+                current = point(&mut tokens)?;
+                subpath.line_to(current);
+                last_handle = None;
+            }
+            'H' => {
+                current.x = tokens.number()? + if relative { current.x } else { 0.0 };
+                subpath.line_to(current);
+                last_handle = None;
+            }
+            'V' => {
+                current.y = tokens.number()? + if relative { current.y } else { 0.0 };
+                subpath.line_to(current);
+                last_handle = None;
+            }
+            'C' | 'S' => {
+                let left = if c.eq_ignore_ascii_case(&'C') {
+                    point(&mut tokens)?
+                } else {
+                    last_handle.map_or(current, |handle| current * 2.0 - handle)
+                };
+                let right = point(&mut tokens)?;
+                current = point(&mut tokens)?;
+                subpath.segments.push(Segment { left, right, end: current });
+                last_handle = Some(right);
+            }
+            other => return Err(format!("the {} command isn't supported", other)),
+        }
+    }
+
+    subpaths.retain(|subpath| !subpath.segments.is_empty());
+    Ok(subpaths)
+}
+
+/// Walks through SVG path data, a command letter or a number at a time.
+struct Tokens<'a>(&'a str);
+
+impl Tokens<'_> {
+    fn skip_separators(&mut self) {
+        self.0 = self.0.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_separators();
+        self.0.is_empty()
+    }
+
+    fn peek_command(&mut self) -> Option<char> {
+        self.skip_separators();
+        self.0.chars().next().filter(|c| c.is_ascii_alphabetic() && *c != 'e' && *c != 'E')
+    }
+
+    fn command(&mut self) -> Option<char> {
+        let c = self.peek_command()?;
+        self.0 = &self.0[1..];
+        Some(c)
+    }
+
+    fn number(&mut self) -> Result<f32, String> {
+        self.skip_separators();
+        let bytes = self.0.as_bytes();
+        let mut end = 0;
+        let mut seen_dot = false;
+        let mut seen_e = false;
+        while end < bytes.len() {
+            match bytes[end] {
+                b'+' | b'-' if end == 0 || matches!(bytes[end - 1], b'e' | b'E') => {}
+                b'0'..=b'9' => {}
+                b'.' if !seen_dot && !seen_e => seen_dot = true,
+                b'e' | b'E' if !seen_e && end > 0 => seen_e = true,
+                _ => break,
+            }
+            end += 1;
+        }
+
+        let (number, rest) = self.0.split_at(end);
+        self.0 = rest;
+        number.parse().map_err(|_| format!("expected a number, found {:?}", number))
+    }
+}
+
+/// The value of every `d` attribute on a `<path>` tag in `svg`.
+fn path_data(svg: &str) -> Vec<&str> {
+    let mut found = vec![];
+    let mut rest = svg;
+    while let Some(start) = rest.find("<path") {
+        rest = &rest[start + "<path".len()..];
+        let tag = &rest[..rest.find('>').unwrap_or(rest.len())];
+
+        let mut attributes = tag;
+        while let Some(at) = attributes.find("d=") {
+            let preceded_by_space = attributes[..at].ends_with(char::is_whitespace);
+            let value = &attributes[at + 2..];
+            attributes = value;
+            if !preceded_by_space {
+                continue;
+            }
+
+            if let Some(quote) = value.chars().next().filter(|&c| c == '"' || c == '\'') {
+                if let Some(len) = value[1..].find(quote) {
+                    found.push(&value[1..1 + len]);
+                }
+            }
+            break;
+        }
+    }
+    found
+}
+
+fn main() {
+    let mut input = None;
+    let mut output = "track.json".to_string();
+    let mut scale = 1.0;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scale" => {
+                let value = args.next().expect("--scale needs a value");
+                scale = value.parse().unwrap_or_else(|_| panic!("{} isn't a number", value));
+            }
+            "--out" => output = args.next().expect("--out needs a file name"),
+            _ if input.is_none() => input = Some(arg),
+            other => panic!("unknown argument {}", other),
+        }
+    }
+    let input = input.expect(
+        "usage: svg-to-track <drawing.svg> [--scale <units per pixel>] [--out <track.json>]",
+    );
+
+    let svg = std::fs::read_to_string(&input).unwrap();
+    let mut tracks = vec![];
+    for d in path_data(&svg) {
+        let subpaths = parse_path(d).unwrap_or_else(|e| panic!("couldn't read a path: {}", e));
+        for subpath in subpaths {
+            println!(
+                "track {}: {} points{}",
+                tracks.len(),
+                subpath.segments.len() + 1,
+                if subpath.closed { ", looped" } else { "" }
+            );
+            tracks.push(subpath.points(scale));
+        }
+    }
+
+    if tracks.is_empty() {
+        panic!("no paths in {}", input);
+    }
+    std::fs::write(&output, BezierPoint::to_json(&tracks)).unwrap();
+    println!("wrote {}", output);
+}